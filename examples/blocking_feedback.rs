@@ -0,0 +1,19 @@
+use anyhow::Result;
+use kobuki_interface::blocking::Kobuki;
+use std::time::Duration;
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut kobuki = Kobuki::open("/dev/kobuki")?;
+    for _ in 0..50 {
+        let feedback = kobuki.recv_feedback(Duration::from_secs(1))?;
+        if let Some(bsd) = feedback.basic_sensor_data {
+            println!("Battery: {} | Bumper: {:?}", bsd.battery, bsd.bumper);
+        }
+    }
+    println!("{:?}", kobuki.latest_state());
+    kobuki.shutdown();
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use anyhow::Result;
+use kobuki_interface::{docking::DockingOptions, robot::Robot};
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    println!("Looking for the docking station...");
+
+    let robot = Robot::open("/dev/kobuki")?;
+    // Wait for the first feedback, so the odometry starts from a known state
+    sleep(Duration::from_millis(500)).await;
+
+    robot.dock(DockingOptions::default()).await?;
+    println!("Docked");
+    robot.beep().await?;
+
+    // allow the last command to be processed before terminating
+    sleep(Duration::from_secs(1)).await;
+
+    Ok(())
+}
@@ -1,22 +1,19 @@
 use anyhow::Result;
 use kobuki_interface::{
+    docking::DockRelation,
     rx::{Feedback, IrSignal},
     serial_port::SerialPortHandler,
     tx::{ByteStream, commands},
 };
 use std::time::Duration;
 use tokio::time::Instant;
-use tokio_serial::SerialPortBuilderExt;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
     println!("Looking for IR signals...");
 
-    let port = tokio_serial::new("/dev/kobuki", 115200)
-        .timeout(Duration::from_millis(1024))
-        .open_native_async()?;
-    let serial = SerialPortHandler::new(port);
+    let serial = SerialPortHandler::builder().open("/dev/kobuki")?;
 
     let mut rx = serial.subscribe();
     let mut last_base_ctrl = Instant::now();
@@ -50,11 +47,12 @@ fn handle_feedback(feedback: &Feedback) {
         .unwrap_or(0.0);
     if let Some(docking_ir) = &feedback.docking_ir {
         println!(
-            "Docking IR: {:4.0} | {} | {} | {}",
+            "Docking IR: {:4.0} | {} | {} | {} | {:?}",
             angle,
             format_ir(docking_ir.left),
             format_ir(docking_ir.center),
-            format_ir(docking_ir.right)
+            format_ir(docking_ir.right),
+            DockRelation::from_ir(docking_ir)
         );
     }
 }
@@ -0,0 +1,25 @@
+use anyhow::Result;
+use kobuki_interface::robot::Robot;
+use std::{f32::consts::PI, time::Duration};
+use tokio::time::sleep;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    println!("Driving 1.5 m forward, turning around and driving back...");
+
+    let robot = Robot::open("/dev/kobuki")?;
+    // Wait for the first feedback, so the odometry starts from a known state
+    sleep(Duration::from_millis(500)).await;
+
+    robot.drive_distance(1.5, 0.3).await?;
+    robot.rotate_by(PI).await?;
+    robot.drive_distance(1.5, 0.3).await?;
+    robot.rotate_to(0.0).await?;
+    robot.beep().await?;
+
+    // allow the last command to be processed before terminating
+    sleep(Duration::from_secs(1)).await;
+
+    Ok(())
+}
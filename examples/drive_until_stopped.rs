@@ -1,55 +1,41 @@
 use anyhow::Result;
 use kobuki_interface::{
+    control::{SafetyEvent, SafetyPolicy},
     serial_port::SerialPortHandler,
     tx::{ByteStream, commands},
 };
 use std::time::Duration;
 use tokio::time::{Instant, sleep};
-use tokio_serial::SerialPortBuilderExt;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
     println!("Driving until the bumper is activated...");
 
-    let port = tokio_serial::new("/dev/kobuki", 115200)
-        .timeout(Duration::from_millis(1024))
-        .open_native_async()?;
-    let serial = SerialPortHandler::new(port);
+    let serial = SerialPortHandler::builder()
+        .safety_policy(SafetyPolicy::AllowReverseOnly)
+        .open("/dev/kobuki")?;
 
-    let mut rx = serial.subscribe();
-    let mut stop = false;
+    // The safety supervisor stops the base when the bumper, cliff or wheel drop sensors trigger
+    let mut events = serial.safety_events();
     let mut last_base_ctrl = Instant::now();
+    serial.set_velocity(100, 0)?;
     loop {
         tokio::select! {
-            feedback = rx.recv() => {
-                if let Some(bsd) = feedback?.basic_sensor_data {
-                    if !bsd.bumper.is_empty() {
-                        println!("Bumper activated: {}", bsd.bumper);
-                        stop = true;
-                    }
-                    if !bsd.wheel_drop.is_empty() {
-                        println!("Wheel drop detected: {}", bsd.wheel_drop);
-                        stop = true;
-                    }
-                }
-                if stop {
+            event = events.recv() => {
+                if let SafetyEvent::Triggered { cause, action } = event? {
+                    println!("Safety supervisor {:?} due to {}", action, cause);
                     break;
                 }
             }
             _ = tokio::time::sleep_until(last_base_ctrl + Duration::from_secs(1)) => {
                 last_base_ctrl = Instant::now();
-                serial
-                    .send_command(ByteStream::builder().subpayload(commands::BaseControl::new(100, 0)))
-                    .await?;
+                serial.set_velocity(100, 0)?;
             }
-
         }
     }
 
-    serial
-        .send_command(ByteStream::builder().subpayload(commands::BaseControl::new(0, 0)))
-        .await?;
+    serial.set_velocity(0, 0)?;
 
     serial
         .send_command(
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use kobuki_interface::{
+    navigation::{PathEvent, PursuitOptions},
+    robot::Robot,
+};
+use std::time::Duration;
+use tokio::time::sleep;
+use tokio_stream::StreamExt;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    println!("Driving around a 1 m square...");
+
+    let robot = Robot::open("/dev/kobuki")?;
+    // Wait for the first feedback, so the odometry starts from a known state
+    sleep(Duration::from_millis(500)).await;
+
+    let square = [(1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+    let mut follower = robot.follow_path(square, PursuitOptions::default());
+    while let Some(event) = follower.next().await {
+        match event {
+            PathEvent::Progress(progress) => println!(
+                "Waypoint {}, {:.2} m left",
+                progress.waypoint, progress.remaining
+            ),
+            PathEvent::Completed => println!("Done at {:?}", robot.pose()),
+            PathEvent::Aborted(error) => return Err(error.into()),
+        }
+    }
+
+    // allow the last command to be processed before terminating
+    sleep(Duration::from_secs(1)).await;
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use kobuki_interface::{
+    odometry::{HeadingSource, Odometry},
+    serial_port::SerialPortHandler,
+};
+use tokio_stream::StreamExt;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    println!("Tracking the pose, push the robot around...");
+
+    let serial = SerialPortHandler::builder().open("/dev/kobuki")?;
+    let mut feedback = serial.feedback_stream();
+    let mut odometry = Odometry::default().heading_source(HeadingSource::Gyro { blend: 0.95 });
+    let mut updates = 0;
+
+    while let Some(feedback) = feedback.next().await {
+        if !odometry.update(&feedback) {
+            continue;
+        }
+        updates += 1;
+        if updates % 10 != 0 {
+            continue;
+        }
+        let pose = odometry.pose();
+        let velocity = odometry.velocity();
+        println!(
+            "x: {:6.3} m | y: {:6.3} m | theta: {:6.1} deg | v: {:5.2} m/s | w: {:5.2} rad/s",
+            pose.x,
+            pose.y,
+            pose.theta.to_degrees(),
+            velocity.linear,
+            velocity.angular
+        );
+    }
+    Ok(())
+}
@@ -1,32 +1,22 @@
 use anyhow::Result;
-use kobuki_interface::{
-    serial_port::SerialPortHandler,
-    tx::{ByteStream, commands},
-};
+use kobuki_interface::serial_port::SerialPortHandler;
 use std::time::Duration;
 use tokio::time::sleep;
-use tokio_serial::SerialPortBuilderExt;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
     println!("Driving a bit...");
 
-    let port = tokio_serial::new("/dev/kobuki", 115200)
-        .timeout(Duration::from_millis(1024))
-        .open_native_async()?;
-    let serial = SerialPortHandler::new(port);
+    let serial = SerialPortHandler::builder().open("/dev/kobuki")?;
 
-    // Notice that the base control needs to be set regularly to keep the robot moving
+    // The handler keeps re-sending the velocity, but stops the robot if it is not refreshed
+    // within the deadman timeout
     for _ in 0..10 {
-        serial
-            .send_command(ByteStream::builder().subpayload(commands::BaseControl::new(100, 100)))
-            .await?;
+        serial.set_velocity(100, 100)?;
         sleep(Duration::from_secs(1)).await;
     }
-    serial
-        .send_command(ByteStream::builder().subpayload(commands::BaseControl::new(0, 0)))
-        .await?;
+    serial.set_velocity(0, 0)?;
 
     // allow the last command to be processed before terminating
     sleep(Duration::from_secs(1)).await;
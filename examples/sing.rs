@@ -4,7 +4,6 @@ use kobuki_interface::{
     tx::{ByteStream, commands},
 };
 use std::time::Duration;
-use tokio_serial::SerialPortBuilderExt;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -31,10 +30,7 @@ async fn main() -> Result<()> {
         (523, 400),
     ];
 
-    let port = tokio_serial::new("/dev/kobuki", 115200)
-        .timeout(Duration::from_millis(1024))
-        .open_native_async()?;
-    let serial = SerialPortHandler::new(port);
+    let serial = SerialPortHandler::builder().open("/dev/kobuki")?;
 
     for (note, duration) in &song {
         let duration = Duration::from_millis(*duration / 2);
@@ -0,0 +1,32 @@
+use anyhow::Result;
+use kobuki_interface::robot::Robot;
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    println!("Driving in a circle, then spinning on the spot...");
+
+    let robot = Robot::open("/dev/kobuki")?;
+    robot.beep().await?;
+
+    for _ in 0..5 {
+        robot.set_twist(0.2, 0.5)?;
+        sleep(Duration::from_secs(1)).await;
+    }
+    for _ in 0..3 {
+        robot.set_twist(0.0, 1.0)?;
+        sleep(Duration::from_secs(1)).await;
+    }
+    robot.stop()?;
+
+    if let Some(inertial) = &robot.state().inertial_sensor {
+        println!("Heading: {:.1} degrees", inertial.value.angle);
+    }
+
+    // allow the last command to be processed before terminating
+    sleep(Duration::from_secs(1)).await;
+
+    Ok(())
+}
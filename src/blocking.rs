@@ -0,0 +1,83 @@
+use crate::{
+    rx::{Feedback, RobotState},
+    serial_port::{FeedbackReceiver, SerialPortHandler, SerialPortHandlerBuilder},
+    tx::ByteStream,
+};
+use std::{io::ErrorKind, time::Duration};
+use tokio::{
+    runtime::{self, Runtime},
+    time::timeout,
+};
+
+/// A blocking handle to a Kobuki base.
+///
+/// Owns an internal runtime which runs the serial task in the background, so the base is kept
+/// alive while the caller is busy elsewhere.
+pub struct Kobuki {
+    handler: SerialPortHandler,
+    feedback: FeedbackReceiver,
+    runtime: Runtime,
+}
+
+impl Kobuki {
+    /// Opens the serial port at `path` using the default configuration.
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        Self::open_with(SerialPortHandler::builder(), path)
+    }
+
+    /// Opens the serial port at `path` using the configuration of `builder`.
+    pub fn open_with(builder: SerialPortHandlerBuilder, path: &str) -> std::io::Result<Self> {
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let handler = {
+            let _guard = runtime.enter();
+            builder.open(path)?
+        };
+        Ok(Self {
+            feedback: handler.feedback_receiver(),
+            handler,
+            runtime,
+        })
+    }
+
+    /// Returns the underlying handler, for the functionality without a blocking counterpart.
+    pub fn handler(&self) -> &SerialPortHandler {
+        &self.handler
+    }
+
+    /// Queues a command, blocking while the command queue is full.
+    pub fn send(&self, cmd: ByteStream) -> std::io::Result<()> {
+        self.runtime.block_on(self.handler.send_command(cmd))
+    }
+
+    /// Sets the velocity target of the base, see [`SerialPortHandler::set_velocity`].
+    pub fn set_velocity(&self, speed: i16, radius: i16) -> std::io::Result<()> {
+        self.handler.set_velocity(speed, radius)
+    }
+
+    /// Waits for the next feedback frame.
+    ///
+    /// Fails with [`ErrorKind::TimedOut`] if no frame arrives within `duration`, and with
+    /// [`ErrorKind::BrokenPipe`] once the serial task has terminated.
+    pub fn recv_feedback(&mut self, duration: Duration) -> std::io::Result<Feedback> {
+        self.runtime
+            .block_on(timeout(duration, self.feedback.recv()))
+            .map_err(|_| std::io::Error::new(ErrorKind::TimedOut, "no feedback received"))?
+            .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "broadcast channel closed"))
+    }
+
+    /// Returns the latest value of every feedback sub-payload, without blocking.
+    pub fn latest_state(&self) -> RobotState {
+        self.handler.robot_state().borrow().clone()
+    }
+
+    /// Stops the base, and shuts down the serial task and the runtime.
+    pub fn shutdown(self) {
+        let Self {
+            handler, runtime, ..
+        } = self;
+        runtime.block_on(handler.shutdown());
+    }
+}
@@ -0,0 +1,175 @@
+use super::{Pid, PidGains, Twist};
+use crate::{
+    odometry::{GyroHeading, Unwrapper},
+    rx::Feedback,
+    tx::commands::BaseControl,
+};
+use std::time::Duration;
+
+/// Largest angular velocity used to correct the heading, in rad/s.
+const MAX_CORRECTION: f32 = 0.5;
+
+/// Keeps the base on a straight line using the gyro.
+///
+/// When the base is commanded to drive straight, the current gyro heading is captured, and
+/// the angular velocity is corrected to keep it. Any other command releases the heading.
+/// The controller is timed by the robot clock of the feedback frames.
+#[derive(Clone, Debug)]
+pub struct HeadingHold {
+    pid: Pid,
+    gyro: GyroHeading,
+    timestamp: Unwrapper,
+    /// Robot time of the latest feedback frame.
+    time: Duration,
+    held: Option<f32>,
+    last_update: Option<Duration>,
+}
+
+impl Default for HeadingHold {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_GAINS)
+    }
+}
+
+impl HeadingHold {
+    /// Gains used by [`HeadingHold::default`].
+    pub const DEFAULT_GAINS: PidGains = PidGains::new(3.0, 0.5, 0.0);
+
+    pub fn new(gains: PidGains) -> Self {
+        Self {
+            pid: Pid::new(gains).output_limit(MAX_CORRECTION),
+            gyro: GyroHeading::default(),
+            timestamp: Unwrapper::new(),
+            time: Duration::ZERO,
+            held: None,
+            last_update: None,
+        }
+    }
+
+    /// Tracks the heading reported by the inertial sensor, and the robot clock.
+    pub fn update(&mut self, feedback: &Feedback) {
+        if let Some(bsd) = &feedback.basic_sensor_data {
+            let elapsed = self.timestamp.update(bsd.timestamp).max(0) as u64;
+            self.time += Duration::from_millis(elapsed);
+        }
+        if let Some(inertial) = &feedback.inertial_sensor {
+            self.gyro.update(inertial.angle);
+        }
+    }
+
+    /// Returns true while a heading is being held.
+    pub fn is_holding(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /// Corrects the angular velocity of a straight twist, or releases the heading otherwise.
+    ///
+    /// The twist is left untouched until the gyro has been read.
+    pub fn apply(&mut self, twist: Twist) -> Twist {
+        if twist.linear == 0.0 || twist.angular != 0.0 || !self.gyro.has_reading() {
+            self.release();
+            return twist;
+        }
+        let heading = self.gyro.heading();
+        let held = *self.held.get_or_insert(heading);
+        let dt = self
+            .last_update
+            .replace(self.time)
+            .map_or(Duration::ZERO, |last| self.time.saturating_sub(last));
+        Twist::new(twist.linear, self.pid.update(held - heading, dt))
+    }
+
+    /// Like [`Self::apply`], for a speed/radius pair.
+    pub fn apply_command(&mut self, cmd: BaseControl) -> BaseControl {
+        if cmd.radius() != 0 {
+            self.release();
+            return cmd;
+        }
+        self.apply(Twist::from(cmd)).to_base_control()
+    }
+
+    /// Forgets the held heading, so the next straight command captures a new one.
+    pub fn release(&mut self) {
+        self.held = None;
+        self.last_update = None;
+        self.pid.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{frame, inertial, inertial_frame, sensors};
+
+    /// A frame at `timestamp` in ms, with the gyro `angle` in degrees.
+    fn gyro_frame(timestamp: u16, angle: f32) -> Feedback {
+        frame(sensors(timestamp, 0, 0), Some(inertial(angle, 0.0)))
+    }
+
+    #[test]
+    fn test_corrects_drift() {
+        let mut hold = HeadingHold::default();
+        let straight = Twist::new(0.3, 0.0);
+        // Without a gyro reading, nothing is held
+        assert_eq!(hold.apply(straight), straight);
+        hold.update(&gyro_frame(0, 179.0));
+        assert_eq!(hold.apply(straight), straight);
+        assert!(hold.is_holding());
+
+        // Drifting clockwise across ±180° is corrected counter-clockwise
+        hold.update(&gyro_frame(100, -178.0));
+        let corrected = hold.apply(straight);
+        assert_eq!(corrected.linear, 0.3);
+        assert!(corrected.angular < 0.0);
+        hold.update(&gyro_frame(200, 178.0));
+        let corrected = hold.apply(straight);
+        assert!(corrected.angular > 0.0 && corrected.angular <= MAX_CORRECTION);
+    }
+
+    #[test]
+    fn test_timed_by_robot_clock() {
+        let gains = PidGains::new(0.0, 1.0, 0.0);
+        let straight = Twist::new(0.3, 0.0);
+        let mut hold = HeadingHold::new(gains);
+        hold.update(&gyro_frame(65500, 0.0));
+        hold.apply(straight);
+        hold.update(&gyro_frame(65500, -1.0));
+        // Without a newer frame, no time has passed, so the integral does not grow
+        assert_eq!(hold.apply(straight).angular, 0.0);
+        assert_eq!(hold.apply(straight).angular, 0.0);
+        // The timestamp wraps around, 100 ms later
+        hold.update(&gyro_frame(64, -1.0));
+        let expected = 1.0f32.to_radians() * 0.1;
+        assert!((hold.apply(straight).angular - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_turning_releases() {
+        let mut hold = HeadingHold::default();
+        hold.update(&inertial_frame(0.0));
+        hold.apply(Twist::new(0.3, 0.0));
+        assert_eq!(hold.apply(Twist::new(0.3, 0.5)), Twist::new(0.3, 0.5));
+        assert!(!hold.is_holding());
+        // The new heading is captured when driving straight again
+        hold.update(&inertial_frame(45.0));
+        assert_eq!(hold.apply(Twist::new(0.3, 0.0)), Twist::new(0.3, 0.0));
+        assert!(hold.is_holding());
+        assert_eq!(
+            hold.apply_command(*BaseControl::new(0, 0)),
+            *BaseControl::new(0, 0)
+        );
+        assert!(!hold.is_holding());
+    }
+
+    #[test]
+    fn test_apply_command() {
+        let mut hold = HeadingHold::default();
+        hold.update(&gyro_frame(0, 0.0));
+        let straight = *BaseControl::new(300, 0);
+        assert_eq!(hold.apply_command(straight), straight);
+        hold.update(&gyro_frame(100, -10.0));
+        let corrected = hold.apply_command(straight);
+        // Turning counter-clockwise, with a positive radius
+        assert!(corrected.radius() > 0, "{:?}", corrected);
+    }
+}
@@ -0,0 +1,17 @@
+mod heading_hold;
+mod mux;
+mod pid;
+mod profile;
+mod smoother;
+mod supervisor;
+mod twist;
+
+pub use heading_hold::HeadingHold;
+pub use mux::{SourceId, VelocityMux};
+pub use pid::{Pid, PidGains};
+pub use profile::{
+    ProfileAxis, ProfileError, ProfileLimits, ProfiledMove, Setpoint, TrapezoidalProfile,
+};
+pub use smoother::{SmootherLimits, VelocitySmoother};
+pub use supervisor::{SafetyAction, SafetyCause, SafetyEvent, SafetyPolicy, SafetySupervisor};
+pub use twist::Twist;
@@ -0,0 +1,225 @@
+use crate::tx::commands::BaseControl;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Identifies a source registered with a [`VelocityMux`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceId(usize);
+
+#[derive(Clone, Debug)]
+struct Source {
+    name: String,
+    priority: u8,
+    timeout: Duration,
+    last: Option<(BaseControl, Instant)>,
+}
+
+impl Source {
+    fn is_fresh(&self, now: Instant) -> bool {
+        self.last
+            .is_some_and(|(_, published)| now.duration_since(published) < self.timeout)
+    }
+}
+
+/// Selects the velocity command of the highest priority source that has published recently.
+///
+/// Each source has a timeout, and loses control when it has not published within it. Between
+/// sources of the same priority, the most recent publication wins.
+#[derive(Clone, Debug, Default)]
+pub struct VelocityMux {
+    /// The registered sources, with `None` in the slots of unregistered ones.
+    sources: Vec<Option<Source>>,
+    /// Slots free for reuse by the next source registered.
+    free: Vec<usize>,
+}
+
+impl VelocityMux {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a source. Higher priorities take precedence over lower ones.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        priority: u8,
+        timeout: Duration,
+    ) -> SourceId {
+        let source = Some(Source {
+            name: name.into(),
+            priority,
+            timeout,
+            last: None,
+        });
+        match self.free.pop() {
+            Some(slot) => {
+                self.sources[slot] = source;
+                SourceId(slot)
+            }
+            None => {
+                self.sources.push(source);
+                SourceId(self.sources.len() - 1)
+            }
+        }
+    }
+
+    /// Removes a source, handing control to the next source in line. Its id may be reused
+    /// by a source registered later.
+    pub fn unregister(&mut self, id: SourceId) {
+        if let Some(slot) = self.sources.get_mut(id.0)
+            && slot.take().is_some()
+        {
+            self.free.push(id.0);
+        }
+    }
+
+    /// Publishes a velocity command from a source.
+    pub fn publish(&mut self, id: SourceId, cmd: BaseControl, now: Instant) {
+        if let Some(source) = self.source_mut(id) {
+            source.last = Some((cmd, now));
+        }
+    }
+
+    /// Gives up control immediately, instead of waiting for the timeout.
+    pub fn release(&mut self, id: SourceId) {
+        if let Some(source) = self.source_mut(id) {
+            source.last = None;
+        }
+    }
+
+    /// Releases every source, so nothing is in control until a source publishes again.
+    pub fn release_all(&mut self) {
+        for source in self.sources.iter_mut().flatten() {
+            source.last = None;
+        }
+    }
+
+    /// Returns the source currently in control, if any.
+    pub fn active(&self, now: Instant) -> Option<SourceId> {
+        self.sources
+            .iter()
+            .enumerate()
+            .filter_map(|(i, source)| Some((i, source.as_ref()?)))
+            .filter(|(_, source)| source.is_fresh(now))
+            .max_by_key(|(_, source)| (source.priority, source.last.map(|(_, t)| t)))
+            .map(|(i, _)| SourceId(i))
+    }
+
+    /// Returns the command of the source currently in control, if any.
+    pub fn command(&self, now: Instant) -> Option<BaseControl> {
+        let id = self.active(now)?;
+        self.source(id)?.last.map(|(cmd, _)| cmd)
+    }
+
+    /// Returns the name a source was registered with.
+    pub fn name(&self, id: SourceId) -> Option<&str> {
+        self.source(id).map(|source| source.name.as_str())
+    }
+
+    /// Returns the number of registered sources.
+    pub fn len(&self) -> usize {
+        self.sources.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn source(&self, id: SourceId) -> Option<&Source> {
+        self.sources.get(id.0)?.as_ref()
+    }
+
+    fn source_mut(&mut self, id: SourceId) -> Option<&mut Source> {
+        self.sources.get_mut(id.0)?.as_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(speed: i16) -> BaseControl {
+        *BaseControl::new(speed, 0)
+    }
+
+    #[test]
+    fn test_no_sources() {
+        let mux = VelocityMux::new();
+        assert_eq!(mux.command(Instant::now()), None);
+    }
+
+    #[test]
+    fn test_highest_priority_wins() {
+        let now = Instant::now();
+        let mut mux = VelocityMux::new();
+        let teleop = mux.register("teleop", 10, Duration::from_millis(500));
+        let navigation = mux.register("navigation", 5, Duration::from_millis(500));
+
+        mux.publish(navigation, cmd(100), now);
+        assert_eq!(mux.active(now), Some(navigation));
+        mux.publish(teleop, cmd(-50), now);
+        mux.publish(navigation, cmd(200), now);
+        assert_eq!(mux.active(now), Some(teleop));
+        assert_eq!(mux.command(now), Some(cmd(-50)));
+        assert_eq!(mux.name(teleop), Some("teleop"));
+    }
+
+    #[test]
+    fn test_timeout_hands_back_control() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut mux = VelocityMux::new();
+        let teleop = mux.register("teleop", 10, Duration::from_millis(500));
+        let navigation = mux.register("navigation", 5, Duration::from_secs(2));
+
+        mux.publish(teleop, cmd(-50), start);
+        mux.publish(navigation, cmd(100), start);
+        assert_eq!(mux.command(at(499)), Some(cmd(-50)));
+        assert_eq!(mux.command(at(500)), Some(cmd(100)));
+        assert_eq!(mux.command(at(2000)), None);
+    }
+
+    #[test]
+    fn test_release() {
+        let now = Instant::now();
+        let mut mux = VelocityMux::new();
+        let teleop = mux.register("teleop", 10, Duration::from_millis(500));
+        let navigation = mux.register("navigation", 5, Duration::from_millis(500));
+        mux.publish(teleop, cmd(-50), now);
+        mux.publish(navigation, cmd(100), now);
+        mux.release(teleop);
+        assert_eq!(mux.active(now), Some(navigation));
+    }
+
+    #[test]
+    fn test_unregister_reuses_slot() {
+        let now = Instant::now();
+        let mut mux = VelocityMux::new();
+        let teleop = mux.register("teleop", 10, Duration::from_millis(500));
+        let navigation = mux.register("navigation", 5, Duration::from_millis(500));
+        mux.publish(teleop, cmd(-50), now);
+        mux.publish(navigation, cmd(100), now);
+        mux.unregister(teleop);
+        mux.unregister(teleop);
+        assert_eq!(mux.active(now), Some(navigation));
+        assert_eq!(mux.name(teleop), None);
+        assert_eq!(mux.len(), 1);
+
+        let docking = mux.register("docking", 20, Duration::from_millis(500));
+        assert_eq!(docking, teleop);
+        assert_eq!(mux.active(now), Some(navigation));
+        assert_eq!(mux.len(), 2);
+    }
+
+    #[test]
+    fn test_same_priority_latest_wins() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut mux = VelocityMux::new();
+        let first = mux.register("first", 0, Duration::from_millis(500));
+        let second = mux.register("second", 0, Duration::from_millis(500));
+        mux.publish(second, cmd(200), start);
+        mux.publish(first, cmd(100), at(10));
+        assert_eq!(mux.command(at(20)), Some(cmd(100)));
+    }
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+/// Gains of a [`Pid`] controller.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PidGains {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+impl PidGains {
+    pub const fn new(kp: f32, ki: f32, kd: f32) -> Self {
+        Self { kp, ki, kd }
+    }
+}
+
+/// A PID controller with output limit and integral anti-windup.
+///
+/// The integral only accumulates while the output is not saturated, or while the error
+/// drives it out of saturation.
+#[derive(Clone, Debug)]
+pub struct Pid {
+    gains: PidGains,
+    output_limit: f32,
+    integral: f32,
+    previous_error: Option<f32>,
+}
+
+impl Pid {
+    pub fn new(gains: PidGains) -> Self {
+        Self {
+            gains,
+            output_limit: f32::INFINITY,
+            integral: 0.0,
+            previous_error: None,
+        }
+    }
+
+    /// Limits the magnitude of the output.
+    pub fn output_limit(mut self, limit: f32) -> Self {
+        self.output_limit = limit.abs();
+        self
+    }
+
+    /// Forgets the accumulated integral and the previous error.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.previous_error = None;
+    }
+
+    /// Returns the output for `error`, measured `dt` after the previous update.
+    pub fn update(&mut self, error: f32, dt: Duration) -> f32 {
+        let dt = dt.as_secs_f32();
+        let derivative = match self.previous_error {
+            Some(previous) if dt > 0.0 => (error - previous) / dt,
+            _ => 0.0,
+        };
+        self.previous_error = Some(error);
+        let output = |integral: f32| {
+            self.gains.kp * error + self.gains.ki * integral + self.gains.kd * derivative
+        };
+        let integral = self.integral + error * dt;
+        let saturated = output(integral).abs() > self.output_limit;
+        if !saturated || output(integral).signum() != error.signum() {
+            self.integral = integral;
+        }
+        output(self.integral).clamp(-self.output_limit, self.output_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_terms() {
+        let mut pid = Pid::new(PidGains::new(2.0, 0.0, 0.0));
+        assert_eq!(pid.update(0.5, DT), 1.0);
+
+        let mut pid = Pid::new(PidGains::new(0.0, 1.0, 0.0));
+        pid.update(1.0, DT);
+        assert!((pid.update(1.0, DT) - 0.2).abs() < 1e-6);
+
+        let mut pid = Pid::new(PidGains::new(0.0, 0.0, 1.0));
+        // No derivative kick on the first update
+        assert_eq!(pid.update(1.0, DT), 0.0);
+        assert!((pid.update(0.5, DT) + 5.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_anti_windup() {
+        let mut pid = Pid::new(PidGains::new(0.0, 1.0, 0.0)).output_limit(0.5);
+        for _ in 0..100 {
+            assert!(pid.update(1.0, DT) <= 0.5);
+        }
+        // The integral is bounded, so it unwinds quickly once the error changes sign
+        for _ in 0..6 {
+            pid.update(-1.0, DT);
+        }
+        assert!(pid.update(-1.0, DT) < 0.0);
+        pid.reset();
+        assert_eq!(pid.update(0.0, DT), 0.0);
+    }
+}
@@ -0,0 +1,370 @@
+use super::Twist;
+use crate::{
+    odometry::Unwrapper,
+    rx::{BasicSensorData, FEEDBACK_PERIOD},
+    tx::commands::BaseControl,
+};
+use std::time::Duration;
+
+/// Velocity and acceleration limits of a [`TrapezoidalProfile`].
+///
+/// Linear limits are in m/s, m/s² and angular limits in rad/s, rad/s².
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProfileLimits {
+    pub max_velocity: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+}
+
+impl ProfileLimits {
+    pub fn new(max_velocity: f32, acceleration: f32, deceleration: f32) -> Self {
+        Self {
+            max_velocity,
+            acceleration,
+            deceleration,
+        }
+    }
+}
+
+/// Why a [`TrapezoidalProfile`] could not be planned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProfileError {
+    /// The distance is infinite or NaN.
+    InvalidDistance(f32),
+    /// A limit is not positive and finite.
+    InvalidLimits(ProfileLimits),
+}
+
+impl std::fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidDistance(distance) => {
+                write!(f, "profile distance must be finite: {}", distance)
+            }
+            Self::InvalidLimits(limits) => {
+                write!(
+                    f,
+                    "profile limits must be positive and finite: {:?}",
+                    limits
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// Position and velocity of a profile at some time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Setpoint {
+    pub position: f32,
+    pub velocity: f32,
+}
+
+/// A move that accelerates, cruises at the maximum velocity and decelerates to a stop.
+///
+/// If the move is too short to reach the maximum velocity, the profile is triangular, and
+/// starts decelerating as soon as it stops accelerating.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrapezoidalProfile {
+    distance: f32,
+    limits: ProfileLimits,
+    peak_velocity: f32,
+    acceleration_time: f32,
+    cruise_time: f32,
+    deceleration_time: f32,
+}
+
+impl TrapezoidalProfile {
+    /// Plans a move over `distance`, like [`Self::try_new`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `distance` is not finite, or if any of the limits is not positive and finite.
+    pub fn new(distance: f32, limits: ProfileLimits) -> Self {
+        Self::try_new(distance, limits).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Plans a move over `distance`, which may be negative.
+    ///
+    /// Fails if `distance` is not finite, or if any of the limits is not positive and finite.
+    pub fn try_new(distance: f32, limits: ProfileLimits) -> Result<Self, ProfileError> {
+        if !distance.is_finite() {
+            return Err(ProfileError::InvalidDistance(distance));
+        }
+        let valid = [
+            limits.max_velocity,
+            limits.acceleration,
+            limits.deceleration,
+        ]
+        .iter()
+        .all(|limit| limit.is_finite() && *limit > 0.0);
+        if !valid {
+            return Err(ProfileError::InvalidLimits(limits));
+        }
+        let length = distance.abs();
+        let (acceleration, deceleration) = (limits.acceleration, limits.deceleration);
+        let ramps = |velocity: f32| {
+            velocity * velocity / (2.0 * acceleration) + velocity * velocity / (2.0 * deceleration)
+        };
+        let peak_velocity = if ramps(limits.max_velocity) <= length {
+            limits.max_velocity
+        } else {
+            (2.0 * length * acceleration * deceleration / (acceleration + deceleration)).sqrt()
+        };
+        let cruise_time = if peak_velocity > 0.0 {
+            (length - ramps(peak_velocity)).max(0.0) / peak_velocity
+        } else {
+            0.0
+        };
+        Ok(Self {
+            distance,
+            limits,
+            peak_velocity,
+            acceleration_time: peak_velocity / acceleration,
+            cruise_time,
+            deceleration_time: peak_velocity / deceleration,
+        })
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Highest velocity reached, which is below the maximum for short moves.
+    pub fn peak_velocity(&self) -> f32 {
+        self.peak_velocity
+    }
+
+    /// Time from the start to the end of the move.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.acceleration_time + self.cruise_time + self.deceleration_time)
+    }
+
+    /// Returns the setpoint at `elapsed` since the start of the move.
+    pub fn sample(&self, elapsed: Duration) -> Setpoint {
+        let t = elapsed.as_secs_f32();
+        let (acceleration, deceleration) = (self.limits.acceleration, self.limits.deceleration);
+        let cruise_end = self.acceleration_time + self.cruise_time;
+        let end = cruise_end + self.deceleration_time;
+        let (position, velocity) = if t < self.acceleration_time {
+            (acceleration * t * t / 2.0, acceleration * t)
+        } else if t < cruise_end {
+            let accelerated = self.peak_velocity * self.acceleration_time / 2.0;
+            (
+                accelerated + self.peak_velocity * (t - self.acceleration_time),
+                self.peak_velocity,
+            )
+        } else if t < end {
+            let left = end - t;
+            (
+                self.distance.abs() - deceleration * left * left / 2.0,
+                deceleration * left,
+            )
+        } else {
+            (self.distance.abs(), 0.0)
+        };
+        Setpoint {
+            position: position.copysign(self.distance),
+            velocity: velocity.copysign(self.distance),
+        }
+    }
+}
+
+/// Whether a profile moves the base forward or rotates it on the spot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileAxis {
+    /// Distance in meters, positive forward.
+    Linear,
+    /// Angle in radians, positive counter-clockwise.
+    Angular,
+}
+
+/// Plays a [`TrapezoidalProfile`] as velocity commands, one per feedback frame.
+///
+/// Time is taken from the robot timestamps, so the setpoints are unaffected by serial latency.
+/// Each command holds the velocity the profile reaches one feedback period later, as it applies
+/// until the next frame.
+#[derive(Clone, Debug)]
+pub struct ProfiledMove {
+    profile: TrapezoidalProfile,
+    axis: ProfileAxis,
+    timestamp: Unwrapper,
+    elapsed: Duration,
+}
+
+impl ProfiledMove {
+    pub fn new(profile: TrapezoidalProfile, axis: ProfileAxis) -> Self {
+        Self {
+            profile,
+            axis,
+            timestamp: Unwrapper::new(),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn profile(&self) -> &TrapezoidalProfile {
+        &self.profile
+    }
+
+    /// Time since the first frame, according to the robot timestamps.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The setpoint as of the last frame.
+    pub fn setpoint(&self) -> Setpoint {
+        self.profile.sample(self.elapsed)
+    }
+
+    /// Advances the move to the timestamp of the frame, and returns the command to send, or
+    /// `None` once the move is over.
+    ///
+    /// The first frame starts the move. A timestamp going backwards, after a reboot of the
+    /// robot, does not advance it.
+    pub fn update(&mut self, bsd: &BasicSensorData) -> Option<BaseControl> {
+        let elapsed = self.timestamp.update(bsd.timestamp).max(0) as u64;
+        self.elapsed += Duration::from_millis(elapsed);
+        if self.elapsed >= self.profile.duration() {
+            return None;
+        }
+        let velocity = self.profile.sample(self.elapsed + FEEDBACK_PERIOD).velocity;
+        let twist = match self.axis {
+            ProfileAxis::Linear => Twist::new(velocity, 0.0),
+            ProfileAxis::Angular => Twist::new(0.0, velocity),
+        };
+        Some(twist.to_base_control())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sensors;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_trapezoid() {
+        // 0.5 s to accelerate over 0.125 m, 0.25 s to decelerate over 0.0625 m
+        let profile = TrapezoidalProfile::new(1.0, ProfileLimits::new(0.5, 1.0, 2.0));
+        assert_eq!(profile.peak_velocity(), 0.5);
+        let cruise = (1.0 - 0.125 - 0.0625) / 0.5;
+        assert_close(profile.duration().as_secs_f32(), 0.75 + cruise);
+
+        let setpoint = profile.sample(Duration::from_millis(250));
+        assert_close(setpoint.velocity, 0.25);
+        assert_close(setpoint.position, 0.03125);
+        let setpoint = profile.sample(Duration::from_secs(1));
+        assert_close(setpoint.velocity, 0.5);
+        assert_close(setpoint.position, 0.125 + 0.25);
+        let setpoint = profile.sample(profile.duration() - Duration::from_millis(125));
+        assert_close(setpoint.velocity, 0.25);
+        assert_close(setpoint.position, 1.0 - 0.015625);
+        assert_eq!(
+            profile.sample(Duration::from_secs(10)),
+            Setpoint {
+                position: 1.0,
+                velocity: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_triangle_backwards() {
+        let profile = TrapezoidalProfile::new(-0.1, ProfileLimits::new(1.0, 1.0, 1.0));
+        assert_close(profile.peak_velocity(), 0.1f32.sqrt());
+        let half = profile.duration() / 2;
+        let setpoint = profile.sample(half);
+        assert_close(setpoint.position, -0.05);
+        assert_close(setpoint.velocity, -(0.1f32.sqrt()));
+
+        let profile = TrapezoidalProfile::new(0.0, ProfileLimits::new(1.0, 1.0, 1.0));
+        assert_eq!(profile.duration(), Duration::ZERO);
+        assert_eq!(profile.sample(Duration::ZERO), Setpoint::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "profile limits must be positive and finite")]
+    fn test_zero_acceleration() {
+        TrapezoidalProfile::new(1.0, ProfileLimits::new(0.5, 0.0, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "profile limits must be positive and finite")]
+    fn test_negative_velocity() {
+        TrapezoidalProfile::new(1.0, ProfileLimits::new(-0.5, 1.0, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "profile limits must be positive and finite")]
+    fn test_zero_velocity() {
+        TrapezoidalProfile::new(1.0, ProfileLimits::new(0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "profile distance must be finite")]
+    fn test_infinite_distance() {
+        TrapezoidalProfile::new(f32::INFINITY, ProfileLimits::new(0.5, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_try_new() {
+        let limits = ProfileLimits::new(0.5, 1.0, f32::INFINITY);
+        assert_eq!(
+            TrapezoidalProfile::try_new(1.0, limits),
+            Err(ProfileError::InvalidLimits(limits))
+        );
+        let limits = ProfileLimits::new(0.5, 1.0, 1.0);
+        assert!(matches!(
+            TrapezoidalProfile::try_new(f32::NAN, limits),
+            Err(ProfileError::InvalidDistance(_))
+        ));
+        assert_eq!(
+            TrapezoidalProfile::try_new(-1.0, limits),
+            Ok(TrapezoidalProfile::new(-1.0, limits))
+        );
+    }
+
+    #[test]
+    fn test_profiled_move() {
+        let profile = TrapezoidalProfile::new(0.2, ProfileLimits::new(0.2, 0.4, 0.4));
+        let mut profiled = ProfiledMove::new(profile, ProfileAxis::Linear);
+        // Follows the robot clock across the wraparound of the timestamp
+        let start = 65000u16;
+        let first = profiled.update(&sensors(start, 0, 0)).unwrap();
+        assert_eq!(first.radius(), 0);
+        assert_eq!(first.speed(), 8);
+        let mut commands = vec![first];
+        let mut i = 1;
+        while let Some(cmd) = profiled.update(&sensors(start.wrapping_add(20 * i), 0, 0)) {
+            commands.push(cmd);
+            i += 1;
+        }
+        assert_eq!(profiled.elapsed(), profile.duration());
+        assert_eq!(profiled.setpoint().position, 0.2);
+        let travelled: f32 = commands.iter().map(|cmd| cmd.speed() as f32 * 0.02).sum();
+        assert!((travelled - 200.0).abs() < 5.0, "{}", travelled);
+    }
+
+    #[test]
+    fn test_angular() {
+        let profile = TrapezoidalProfile::new(-1.0, ProfileLimits::new(1.0, 2.0, 2.0));
+        let mut profiled = ProfiledMove::new(profile, ProfileAxis::Angular);
+        profiled.update(&sensors(0, 0, 0));
+        let cmd = profiled.update(&sensors(500, 0, 0)).unwrap();
+        // Spinning clockwise on the spot
+        assert_eq!(cmd.radius(), 1);
+        assert!(cmd.speed() < 0);
+        // A timestamp going backwards does not advance the move
+        profiled.update(&sensors(100, 0, 0));
+        assert_eq!(profiled.elapsed(), Duration::from_millis(500));
+    }
+}
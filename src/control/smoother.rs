@@ -0,0 +1,302 @@
+use super::Twist;
+use crate::tx::commands::BaseControl;
+use std::time::Duration;
+
+/// Acceleration and jerk limits of a [`VelocitySmoother`].
+///
+/// Linear limits are in m/s², m/s³ and angular limits in rad/s², rad/s³. Use
+/// `f32::INFINITY` to disable a limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmootherLimits {
+    /// Limit when the linear speed increases.
+    pub linear_acceleration: f32,
+    /// Limit when the linear speed decreases towards a non-zero target.
+    pub linear_deceleration: f32,
+    /// Limit when the linear speed decreases towards zero.
+    pub linear_stop_deceleration: f32,
+    pub linear_jerk: f32,
+    /// Limit when the angular speed increases.
+    pub angular_acceleration: f32,
+    /// Limit when the angular speed decreases towards a non-zero target.
+    pub angular_deceleration: f32,
+    /// Limit when the angular speed decreases towards zero.
+    pub angular_stop_deceleration: f32,
+    pub angular_jerk: f32,
+}
+
+impl Default for SmootherLimits {
+    fn default() -> Self {
+        Self {
+            linear_acceleration: 0.5,
+            linear_deceleration: 0.8,
+            linear_stop_deceleration: 1.5,
+            linear_jerk: 5.0,
+            angular_acceleration: 3.0,
+            angular_deceleration: 4.0,
+            angular_stop_deceleration: 6.0,
+            angular_jerk: 30.0,
+        }
+    }
+}
+
+/// Ramps velocity commands, so the base does not lurch when the commanded velocity changes.
+///
+/// Call [`VelocitySmoother::step`] at a fixed rate with the latest target velocity, and send
+/// the returned velocity to the base.
+#[derive(Clone, Debug)]
+pub struct VelocitySmoother {
+    limits: SmootherLimits,
+    linear: Axis,
+    angular: Axis,
+}
+
+impl VelocitySmoother {
+    pub fn new(limits: SmootherLimits) -> Self {
+        Self {
+            limits,
+            linear: Axis::default(),
+            angular: Axis::default(),
+        }
+    }
+
+    /// The velocity most recently returned by [`Self::step`].
+    pub fn current(&self) -> Twist {
+        Twist::new(self.linear.velocity, self.angular.velocity)
+    }
+
+    /// Sets the current velocity, e.g. after the base has been stopped by other means.
+    pub fn reset(&mut self, current: Twist) {
+        self.linear = Axis {
+            velocity: current.linear,
+            acceleration: 0.0,
+        };
+        self.angular = Axis {
+            velocity: current.angular,
+            acceleration: 0.0,
+        };
+    }
+
+    /// Moves the velocity towards `target` as far as the limits allow within `dt`.
+    ///
+    /// Both velocities cover the same fraction of the way to the target, so the base keeps
+    /// turning along the radius of the target while ramping.
+    pub fn step(&mut self, target: Twist, dt: Duration) -> Twist {
+        let dt = dt.as_secs_f32();
+        let previous = self.current();
+        let limits = &self.limits;
+        self.linear.step(
+            target.linear,
+            dt,
+            AxisLimits {
+                acceleration: limits.linear_acceleration,
+                deceleration: limits.linear_deceleration,
+                stop_deceleration: limits.linear_stop_deceleration,
+                jerk: limits.linear_jerk,
+            },
+        );
+        self.angular.step(
+            target.angular,
+            dt,
+            AxisLimits {
+                acceleration: limits.angular_acceleration,
+                deceleration: limits.angular_deceleration,
+                stop_deceleration: limits.angular_stop_deceleration,
+                jerk: limits.angular_jerk,
+            },
+        );
+        let progress = self
+            .linear
+            .progress(previous.linear, target.linear)
+            .min(self.angular.progress(previous.angular, target.angular));
+        self.linear
+            .scale(previous.linear, target.linear, progress, dt);
+        self.angular
+            .scale(previous.angular, target.angular, progress, dt);
+        self.current()
+    }
+
+    /// Same as [`Self::step`], but with speed/radius commands.
+    pub fn step_command(&mut self, target: BaseControl, dt: Duration) -> BaseControl {
+        self.step(Twist::from(target), dt).to_base_control()
+    }
+}
+
+struct AxisLimits {
+    acceleration: f32,
+    deceleration: f32,
+    stop_deceleration: f32,
+    jerk: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Axis {
+    velocity: f32,
+    acceleration: f32,
+}
+
+impl Axis {
+    fn step(&mut self, target: f32, dt: f32, limits: AxisLimits) {
+        let error = target - self.velocity;
+        if error == 0.0 || dt <= 0.0 {
+            self.acceleration = 0.0;
+            return;
+        }
+
+        // Slowing down if the velocity moves towards zero
+        let limit = if self.velocity != 0.0 && error.signum() != self.velocity.signum() {
+            if target == 0.0 {
+                limits.stop_deceleration
+            } else {
+                limits.deceleration
+            }
+        } else {
+            limits.acceleration
+        };
+
+        // Leave room to ramp the acceleration back down before reaching the target
+        let limit = limit.min((2.0 * limits.jerk * error.abs()).sqrt());
+        let wanted = (error / dt).clamp(-limit, limit);
+        let max_change = limits.jerk * dt;
+        self.acceleration += (wanted - self.acceleration).clamp(-max_change, max_change);
+
+        let velocity = self.velocity + self.acceleration * dt;
+        if (target - velocity).signum() != error.signum() {
+            self.velocity = target;
+            self.acceleration = 0.0;
+        } else {
+            self.velocity = velocity;
+        }
+    }
+
+    /// Fraction of the way from `previous` to `target` covered by the last step.
+    fn progress(&self, previous: f32, target: f32) -> f32 {
+        if target == previous {
+            return 1.0;
+        }
+        (self.velocity - previous) / (target - previous)
+    }
+
+    /// Goes back on the last step, so it only covers `progress` of the way to `target`.
+    fn scale(&mut self, previous: f32, target: f32, progress: f32, dt: f32) {
+        if self.progress(previous, target) <= progress || dt <= 0.0 {
+            return;
+        }
+        self.velocity = previous + progress * (target - previous);
+        self.acceleration = (self.velocity - previous) / dt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: Duration = Duration::from_millis(100);
+
+    fn no_jerk_limits() -> SmootherLimits {
+        SmootherLimits {
+            linear_acceleration: 0.5,
+            linear_deceleration: 1.0,
+            linear_stop_deceleration: 2.0,
+            linear_jerk: f32::INFINITY,
+            angular_acceleration: 2.0,
+            angular_deceleration: 2.0,
+            angular_stop_deceleration: 4.0,
+            angular_jerk: f32::INFINITY,
+        }
+    }
+
+    fn run(smoother: &mut VelocitySmoother, target: BaseControl, steps: usize) -> Vec<BaseControl> {
+        (0..steps)
+            .map(|_| smoother.step_command(target, DT))
+            .collect()
+    }
+
+    fn speeds(commands: &[BaseControl]) -> Vec<i16> {
+        commands.iter().map(|cmd| cmd.speed()).collect()
+    }
+
+    #[test]
+    fn test_ramp_up_straight() {
+        let mut smoother = VelocitySmoother::new(no_jerk_limits());
+        let commands = run(&mut smoother, *BaseControl::new(200, 0), 6);
+        assert_eq!(speeds(&commands), vec![50, 100, 150, 200, 200, 200]);
+        assert!(commands.iter().all(|cmd| cmd.radius() == 0));
+    }
+
+    #[test]
+    fn test_stop_is_faster_than_ramp_up() {
+        let mut smoother = VelocitySmoother::new(no_jerk_limits());
+        run(&mut smoother, *BaseControl::new(400, 0), 8);
+        let commands = run(&mut smoother, *BaseControl::new(0, 0), 3);
+        assert_eq!(speeds(&commands), vec![200, 0, 0]);
+    }
+
+    #[test]
+    fn test_slow_down_to_non_zero() {
+        let mut smoother = VelocitySmoother::new(no_jerk_limits());
+        run(&mut smoother, *BaseControl::new(400, 0), 8);
+        let commands = run(&mut smoother, *BaseControl::new(100, 0), 4);
+        assert_eq!(speeds(&commands), vec![300, 200, 100, 100]);
+    }
+
+    #[test]
+    fn test_reverse_decelerates_through_zero() {
+        let mut smoother = VelocitySmoother::new(no_jerk_limits());
+        run(&mut smoother, *BaseControl::new(100, 0), 2);
+        let commands = run(&mut smoother, *BaseControl::new(-100, 0), 3);
+        assert_eq!(speeds(&commands), vec![0, -50, -100]);
+    }
+
+    #[test]
+    fn test_ramp_up_rotation() {
+        let mut smoother = VelocitySmoother::new(no_jerk_limits());
+        // 1 rad/s on the spot
+        let commands = run(&mut smoother, *BaseControl::new(115, 1), 6);
+        assert_eq!(speeds(&commands), vec![23, 46, 69, 92, 115, 115]);
+        assert!(commands.iter().all(|cmd| cmd.radius() == 1));
+    }
+
+    #[test]
+    fn test_arc_keeps_radius() {
+        let mut smoother = VelocitySmoother::new(no_jerk_limits());
+        // The angular velocity could ramp up faster than the linear one
+        let target = Twist::new(0.4, 1.0).to_base_control();
+        let commands = run(&mut smoother, target, 10);
+        for cmd in &commands[..7] {
+            assert!(
+                (cmd.radius() - target.radius()).abs() <= 1,
+                "{:?}",
+                commands
+            );
+        }
+        assert_eq!(*commands.last().unwrap(), target);
+        assert_eq!(speeds(&commands)[..3], [64, 129, 193]);
+    }
+
+    #[test]
+    fn test_jerk_limits_change_of_acceleration() {
+        let mut limits = no_jerk_limits();
+        limits.linear_jerk = 2.5;
+        let mut smoother = VelocitySmoother::new(limits);
+        let commands = run(&mut smoother, *BaseControl::new(500, 0), 30);
+        let speeds = speeds(&commands);
+
+        // The acceleration starts at zero and increases by at most 0.25 m/s² per step
+        assert_eq!(speeds[..3], [25, 75, 125]);
+        // It never exceeds the acceleration limit, or overshoots the target
+        for pair in speeds.windows(2) {
+            assert!(pair[1] - pair[0] <= 50, "{:?}", speeds);
+            assert!(pair[1] <= 500);
+        }
+        assert_eq!(*speeds.last().unwrap(), 500);
+    }
+
+    #[test]
+    fn test_default_limits_reach_target() {
+        let mut smoother = VelocitySmoother::new(SmootherLimits::default());
+        let commands = run(&mut smoother, *BaseControl::new(300, 0), 30);
+        assert_eq!(*commands.last().unwrap(), *BaseControl::new(300, 0));
+        let commands = run(&mut smoother, *BaseControl::new(0, 0), 30);
+        assert_eq!(*commands.last().unwrap(), *BaseControl::new(0, 0));
+    }
+}
@@ -0,0 +1,382 @@
+use super::Twist;
+use crate::{
+    rx::{BasicSensorData, Sides, SidesCentral},
+    tx::commands::BaseControl,
+};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How the [`SafetySupervisor`] reacts to a bumper or cliff sensor being triggered.
+///
+/// Wheel drop and wheel overcurrent always stop the base, regardless of the policy.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SafetyPolicy {
+    /// Stop, and block all motion until the sensors are clear.
+    Stop,
+    /// Stop, reverse at `speed` mm/s for `duration`, and then only allow reversing until the
+    /// sensors are clear.
+    BackOff { speed: i16, duration: Duration },
+    /// Stop, and only allow reversing until the sensors are clear.
+    AllowReverseOnly,
+}
+
+/// The sensor reading that made the supervisor intervene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SafetyCause {
+    Bumper(SidesCentral),
+    Cliff(SidesCentral),
+    WheelDrop(Sides),
+    Overcurrent(Sides),
+}
+
+impl std::fmt::Display for SafetyCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bumper(sides) => write!(f, "bumper ({})", sides),
+            Self::Cliff(sides) => write!(f, "cliff ({})", sides),
+            Self::WheelDrop(sides) => write!(f, "wheel drop ({})", sides),
+            Self::Overcurrent(sides) => write!(f, "overcurrent ({})", sides),
+        }
+    }
+}
+
+/// What the supervisor did in response to a [`SafetyCause`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SafetyAction {
+    /// The base was stopped, and all motion is blocked.
+    Stopped,
+    /// The base was stopped and is reversing away from the hazard.
+    BackingOff,
+    /// The base was stopped, and only reversing is allowed.
+    ReverseOnly,
+}
+
+/// An intervention of the [`SafetySupervisor`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SafetyEvent {
+    /// A hazard was detected.
+    Triggered {
+        cause: SafetyCause,
+        action: SafetyAction,
+    },
+    /// A velocity command was replaced because of an active hazard.
+    CommandBlocked {
+        requested: BaseControl,
+        sent: BaseControl,
+    },
+    /// All hazards are gone, and motion is no longer restricted.
+    Cleared,
+}
+
+/// Restricts the velocity commands sent to the base while a bumper, cliff, wheel drop or
+/// wheel overcurrent is reported by the sensors.
+#[derive(Clone, Debug)]
+pub struct SafetySupervisor {
+    policy: SafetyPolicy,
+    bumper: SidesCentral,
+    cliff: SidesCentral,
+    wheel_drop: Sides,
+    overcurrent: Sides,
+    back_off_until: Option<Instant>,
+    last_blocked: Option<BaseControl>,
+}
+
+impl SafetySupervisor {
+    pub fn new(policy: SafetyPolicy) -> Self {
+        Self {
+            policy,
+            bumper: SidesCentral::empty(),
+            cliff: SidesCentral::empty(),
+            wheel_drop: Sides::empty(),
+            overcurrent: Sides::empty(),
+            back_off_until: None,
+            last_blocked: None,
+        }
+    }
+
+    pub fn policy(&self) -> SafetyPolicy {
+        self.policy
+    }
+
+    /// Returns true if no hazard is active.
+    pub fn is_clear(&self) -> bool {
+        self.bumper.is_empty() && self.cliff.is_empty() && !self.is_critical()
+    }
+
+    /// Updates the hazards from the latest sensor data, and returns the resulting events.
+    pub fn update(&mut self, bsd: &BasicSensorData, now: Instant) -> Vec<SafetyEvent> {
+        let was_clear = self.is_clear();
+        let mut causes = Vec::new();
+        let new_wheel_drop = bsd.wheel_drop - self.wheel_drop;
+        if !new_wheel_drop.is_empty() {
+            causes.push(SafetyCause::WheelDrop(new_wheel_drop));
+        }
+        let new_overcurrent = bsd.overcurrent_wheel - self.overcurrent;
+        if !new_overcurrent.is_empty() {
+            causes.push(SafetyCause::Overcurrent(new_overcurrent));
+        }
+        let new_bumper = bsd.bumper - self.bumper;
+        if !new_bumper.is_empty() {
+            causes.push(SafetyCause::Bumper(new_bumper));
+        }
+        let new_cliff = bsd.cliff - self.cliff;
+        if !new_cliff.is_empty() {
+            causes.push(SafetyCause::Cliff(new_cliff));
+        }
+
+        self.bumper = bsd.bumper;
+        self.cliff = bsd.cliff;
+        self.wheel_drop = bsd.wheel_drop;
+        self.overcurrent = bsd.overcurrent_wheel;
+
+        let mut events: Vec<_> = causes
+            .into_iter()
+            .map(|cause| SafetyEvent::Triggered {
+                cause,
+                action: self.react(cause, now),
+            })
+            .collect();
+        if !was_clear && self.is_clear() {
+            self.last_blocked = None;
+            events.push(SafetyEvent::Cleared);
+        }
+        events
+    }
+
+    /// Returns a command that must be sent regardless of the producers, e.g. while backing off.
+    ///
+    /// When backing off has finished, a single stop command is returned.
+    pub fn override_command(&mut self, now: Instant) -> Option<BaseControl> {
+        let until = self.back_off_until?;
+        if now < until && !self.is_critical() {
+            return Some(self.back_off_command());
+        }
+        self.back_off_until = None;
+        Some(*BaseControl::new(0, 0))
+    }
+
+    /// Returns the command to send instead of `cmd`, and an event if `cmd` was blocked.
+    pub fn filter(&mut self, cmd: BaseControl, now: Instant) -> (BaseControl, Option<SafetyEvent>) {
+        let sent = self.allowed(cmd, now);
+        if sent == cmd {
+            self.last_blocked = None;
+            return (sent, None);
+        }
+        // Only report the first time a command is blocked, as producers tend to repeat it
+        if self.last_blocked == Some(cmd) {
+            return (sent, None);
+        }
+        self.last_blocked = Some(cmd);
+        let event = SafetyEvent::CommandBlocked {
+            requested: cmd,
+            sent,
+        };
+        (sent, Some(event))
+    }
+
+    fn allowed(&self, cmd: BaseControl, now: Instant) -> BaseControl {
+        let stop = *BaseControl::new(0, 0);
+        if self.is_critical() {
+            return stop;
+        }
+        if self.back_off_until.is_some_and(|until| now < until) {
+            return self.back_off_command();
+        }
+        if self.bumper.is_empty() && self.cliff.is_empty() {
+            return cmd;
+        }
+        let reversing = Twist::from(cmd).linear < 0.0;
+        match self.policy {
+            SafetyPolicy::Stop => stop,
+            SafetyPolicy::BackOff { .. } | SafetyPolicy::AllowReverseOnly if reversing => cmd,
+            SafetyPolicy::BackOff { .. } | SafetyPolicy::AllowReverseOnly => stop,
+        }
+    }
+
+    fn react(&mut self, cause: SafetyCause, now: Instant) -> SafetyAction {
+        match (cause, self.policy) {
+            (SafetyCause::WheelDrop(_) | SafetyCause::Overcurrent(_), _) => {
+                self.back_off_until = None;
+                SafetyAction::Stopped
+            }
+            (_, _) if self.is_critical() => SafetyAction::Stopped,
+            (_, SafetyPolicy::Stop) => SafetyAction::Stopped,
+            (_, SafetyPolicy::AllowReverseOnly) => SafetyAction::ReverseOnly,
+            (_, SafetyPolicy::BackOff { duration, .. }) => {
+                if self.back_off_until.is_none() {
+                    self.back_off_until = Some(now + duration);
+                }
+                SafetyAction::BackingOff
+            }
+        }
+    }
+
+    fn back_off_command(&self) -> BaseControl {
+        match self.policy {
+            SafetyPolicy::BackOff { speed, .. } => *BaseControl::new(-speed.abs(), 0),
+            _ => *BaseControl::new(0, 0),
+        }
+    }
+
+    fn is_critical(&self) -> bool {
+        !self.wheel_drop.is_empty() || !self.overcurrent.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensors(bumper: SidesCentral, cliff: SidesCentral, wheel_drop: Sides) -> BasicSensorData {
+        BasicSensorData {
+            bumper,
+            cliff,
+            wheel_drop,
+            ..Default::default()
+        }
+    }
+
+    fn clear() -> BasicSensorData {
+        BasicSensorData::default()
+    }
+
+    fn forward() -> BaseControl {
+        *BaseControl::new(200, 0)
+    }
+
+    fn reverse() -> BaseControl {
+        *BaseControl::new(-100, 0)
+    }
+
+    fn stop() -> BaseControl {
+        *BaseControl::new(0, 0)
+    }
+
+    #[test]
+    fn test_clear_allows_everything() {
+        let now = Instant::now();
+        let mut supervisor = SafetySupervisor::new(SafetyPolicy::Stop);
+        assert!(supervisor.update(&clear(), now).is_empty());
+        assert_eq!(supervisor.filter(forward(), now), (forward(), None));
+        assert_eq!(supervisor.override_command(now), None);
+    }
+
+    #[test]
+    fn test_stop_policy_blocks_all_motion() {
+        let now = Instant::now();
+        let mut supervisor = SafetySupervisor::new(SafetyPolicy::Stop);
+        let events = supervisor.update(
+            &sensors(SidesCentral::CENTRAL, SidesCentral::empty(), Sides::empty()),
+            now,
+        );
+        assert_eq!(
+            events,
+            vec![SafetyEvent::Triggered {
+                cause: SafetyCause::Bumper(SidesCentral::CENTRAL),
+                action: SafetyAction::Stopped,
+            }]
+        );
+        assert_eq!(supervisor.filter(reverse(), now).0, stop());
+        assert_eq!(supervisor.filter(forward(), now).0, stop());
+
+        assert_eq!(supervisor.update(&clear(), now), vec![SafetyEvent::Cleared]);
+        assert_eq!(supervisor.filter(forward(), now), (forward(), None));
+    }
+
+    #[test]
+    fn test_allow_reverse_only() {
+        let now = Instant::now();
+        let mut supervisor = SafetySupervisor::new(SafetyPolicy::AllowReverseOnly);
+        supervisor.update(
+            &sensors(SidesCentral::empty(), SidesCentral::LEFT, Sides::empty()),
+            now,
+        );
+        assert_eq!(supervisor.filter(reverse(), now), (reverse(), None));
+        // Turning on the spot is not reversing
+        assert_eq!(supervisor.filter(*BaseControl::new(100, 1), now).0, stop());
+        assert_eq!(
+            supervisor.filter(forward(), now),
+            (
+                stop(),
+                Some(SafetyEvent::CommandBlocked {
+                    requested: forward(),
+                    sent: stop(),
+                })
+            )
+        );
+        // Repeated commands are only reported once
+        assert_eq!(supervisor.filter(forward(), now), (stop(), None));
+    }
+
+    #[test]
+    fn test_back_off() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut supervisor = SafetySupervisor::new(SafetyPolicy::BackOff {
+            speed: 50,
+            duration: Duration::from_millis(500),
+        });
+        let events = supervisor.update(
+            &sensors(SidesCentral::RIGHT, SidesCentral::empty(), Sides::empty()),
+            start,
+        );
+        assert_eq!(
+            events,
+            vec![SafetyEvent::Triggered {
+                cause: SafetyCause::Bumper(SidesCentral::RIGHT),
+                action: SafetyAction::BackingOff,
+            }]
+        );
+        let back_off = *BaseControl::new(-50, 0);
+        assert_eq!(supervisor.override_command(at(100)), Some(back_off));
+        assert_eq!(supervisor.filter(forward(), at(100)).0, back_off);
+
+        // The bumper is released as soon as the base reverses, but backing off continues
+        assert_eq!(
+            supervisor.update(&clear(), at(200)),
+            vec![SafetyEvent::Cleared]
+        );
+        assert_eq!(supervisor.override_command(at(400)), Some(back_off));
+        assert_eq!(supervisor.filter(forward(), at(400)).0, back_off);
+        assert_eq!(supervisor.override_command(at(500)), Some(stop()));
+        assert_eq!(supervisor.override_command(at(600)), None);
+        assert_eq!(supervisor.filter(forward(), at(600)), (forward(), None));
+    }
+
+    #[test]
+    fn test_wheel_drop_stops_regardless_of_policy() {
+        let now = Instant::now();
+        let mut supervisor = SafetySupervisor::new(SafetyPolicy::AllowReverseOnly);
+        let events = supervisor.update(
+            &sensors(SidesCentral::empty(), SidesCentral::empty(), Sides::LEFT),
+            now,
+        );
+        assert_eq!(
+            events,
+            vec![SafetyEvent::Triggered {
+                cause: SafetyCause::WheelDrop(Sides::LEFT),
+                action: SafetyAction::Stopped,
+            }]
+        );
+        assert_eq!(supervisor.filter(reverse(), now).0, stop());
+    }
+
+    #[test]
+    fn test_overcurrent_stops() {
+        let now = Instant::now();
+        let mut supervisor = SafetySupervisor::new(SafetyPolicy::AllowReverseOnly);
+        let bsd = BasicSensorData {
+            overcurrent_wheel: Sides::RIGHT,
+            ..Default::default()
+        };
+        assert_eq!(
+            supervisor.update(&bsd, now),
+            vec![SafetyEvent::Triggered {
+                cause: SafetyCause::Overcurrent(Sides::RIGHT),
+                action: SafetyAction::Stopped,
+            }]
+        );
+        assert_eq!(supervisor.filter(forward(), now).0, stop());
+        assert!(!supervisor.is_clear());
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{odometry::WheelGeometry, tx::commands::BaseControl};
+
+const WHEELBASE: f32 = WheelGeometry::KOBUKI.wheelbase;
+
+/// Linear and angular velocity of the base.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Twist {
+    /// Forward velocity in m/s.
+    pub linear: f32,
+    /// Counter-clockwise angular velocity in rad/s.
+    pub angular: f32,
+}
+
+impl Twist {
+    pub fn new(linear: f32, angular: f32) -> Self {
+        Self { linear, angular }
+    }
+
+    pub fn zero() -> Self {
+        Self::default()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.linear == 0.0 && self.angular == 0.0
+    }
+
+    /// Speed of the outer wheel in m/s, which is what the firmware limits.
+    pub fn wheel_speed(&self) -> f32 {
+        self.linear.abs() + self.angular.abs() * WHEELBASE / 2.0
+    }
+
+    /// Scales the twist down until neither velocity exceeds its limit, and the outer wheel
+    /// does not exceed `max_linear`.
+    ///
+    /// Both velocities are scaled by the same factor, so the radius of the turn is kept.
+    pub fn clamp(&self, max_linear: f32, max_angular: f32) -> Self {
+        let scale = (max_linear / self.linear.abs())
+            .min(max_angular / self.angular.abs())
+            .min(max_linear / self.wheel_speed())
+            .min(1.0);
+        Self::new(self.linear * scale, self.angular * scale)
+    }
+
+    /// Converts a speed/radius pair as understood by the firmware into a twist.
+    pub fn from_base_control(cmd: &BaseControl) -> Self {
+        let speed = cmd.speed() as f32 / 1000.0;
+        match cmd.radius() {
+            0 => Self::new(speed, 0.0),
+            1 => Self::new(0.0, 2.0 * speed / WHEELBASE),
+            radius => {
+                let radius = radius as f32 / 1000.0;
+                // The speed is the speed of the outer wheel
+                let angular = if radius > 0.0 {
+                    speed / (radius + WHEELBASE / 2.0)
+                } else {
+                    speed / (radius - WHEELBASE / 2.0)
+                };
+                Self::new(radius * angular, angular)
+            }
+        }
+    }
+
+    /// Converts the twist into the speed/radius pair understood by the firmware.
+    ///
+    /// The firmware uses a radius of 0 for driving straight, and a radius of 1 for turning on
+    /// the spot. Otherwise the speed is the speed of the outer wheel.
+    pub fn to_base_control(&self) -> BaseControl {
+        const EPSILON: f32 = 0.0001;
+        let mm = |meters: f32| {
+            (meters * 1000.0)
+                .round()
+                .clamp(i16::MIN as f32, i16::MAX as f32)
+        };
+
+        if self.angular.abs() < EPSILON {
+            return *BaseControl::new(mm(self.linear) as i16, 0);
+        }
+        let radius = self.linear / self.angular;
+        if self.linear.abs() < EPSILON || radius.abs() <= 0.001 {
+            return *BaseControl::new(mm(self.angular * WHEELBASE / 2.0) as i16, 1);
+        }
+        if radius.abs() * 1000.0 > i16::MAX as f32 {
+            // The turn is too wide to be represented, so drive straight instead
+            return *BaseControl::new(mm(self.linear) as i16, 0);
+        }
+        let speed = if radius > 0.0 {
+            (radius + WHEELBASE / 2.0) * self.angular
+        } else {
+            (radius - WHEELBASE / 2.0) * self.angular
+        };
+        *BaseControl::new(mm(speed) as i16, mm(radius) as i16)
+    }
+}
+
+impl From<BaseControl> for Twist {
+    fn from(cmd: BaseControl) -> Self {
+        Self::from_base_control(&cmd)
+    }
+}
+
+impl From<Twist> for BaseControl {
+    fn from(twist: Twist) -> Self {
+        twist.to_base_control()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Twist, expected: Twist) {
+        assert!(
+            (actual.linear - expected.linear).abs() < 0.002
+                && (actual.angular - expected.angular).abs() < 0.01,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_straight() {
+        let cmd = Twist::new(0.25, 0.0).to_base_control();
+        assert_eq!(cmd, *BaseControl::new(250, 0));
+        assert_eq!(Twist::from(cmd), Twist::new(0.25, 0.0));
+    }
+
+    #[test]
+    fn test_pure_rotation() {
+        let cmd = Twist::new(0.0, 1.0).to_base_control();
+        assert_eq!(cmd, *BaseControl::new(115, 1));
+        assert_close(Twist::from(cmd), Twist::new(0.0, 1.0));
+
+        let cmd = Twist::new(0.0, -1.0).to_base_control();
+        assert_eq!(cmd, *BaseControl::new(-115, 1));
+    }
+
+    #[test]
+    fn test_arc() {
+        for twist in [
+            Twist::new(0.2, 0.5),
+            Twist::new(0.2, -0.5),
+            Twist::new(-0.2, 0.5),
+            Twist::new(-0.2, -0.5),
+        ] {
+            let cmd = twist.to_base_control();
+            assert_eq!(cmd.radius().abs(), 400);
+            assert_close(Twist::from(cmd), twist);
+        }
+    }
+
+    #[test]
+    fn test_wide_arc_drives_straight() {
+        let cmd = Twist::new(0.5, 0.001).to_base_control();
+        assert_eq!(cmd, *BaseControl::new(500, 0));
+    }
+
+    #[test]
+    fn test_clamp() {
+        assert_eq!(Twist::new(-0.2, 1.0).clamp(0.7, 3.0), Twist::new(-0.2, 1.0));
+        assert_eq!(Twist::new(0.0, -4.0).clamp(0.7, 3.0), Twist::new(0.0, -3.0));
+        assert_eq!(Twist::new(1.2, 0.0).clamp(0.7, 3.0), Twist::new(0.7, 0.0));
+
+        // The outer wheel is limited, keeping the radius of the turn
+        let twist = Twist::new(1.2, -4.0).clamp(0.7, 3.0);
+        assert!((twist.wheel_speed() - 0.7).abs() < 1e-6, "{:?}", twist);
+        assert!((twist.linear / twist.angular - 1.2 / -4.0).abs() < 1e-6);
+        let cmd = twist.to_base_control();
+        assert_eq!(cmd.speed(), 700);
+        assert_eq!(cmd.radius(), -300);
+    }
+}
@@ -0,0 +1,630 @@
+use super::{BearingSector, DockDistance, DockInterpreter, DockRegion, DockRelation};
+use crate::{
+    control::Twist,
+    odometry::{Pose, normalize_angle},
+    rx::{BasicSensorData, Charger, DockingIr},
+    tx::commands::BaseControl,
+};
+use std::f32::consts::PI;
+
+/// Why docking was given up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DockingFailure {
+    /// No dock signal was seen while rotating.
+    NotFound,
+    /// The base kept bumping into something other than the charging contacts, or missing
+    /// the center beam.
+    TooManyRetries,
+}
+
+impl std::fmt::Display for DockingFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "dock not found"),
+            Self::TooManyRetries => write!(f, "too many docking attempts"),
+        }
+    }
+}
+
+/// Progress of a [`DockingController`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DockingState {
+    /// Not started.
+    #[default]
+    Idle,
+    /// Rotating on the spot, looking for the dock.
+    Scan,
+    /// Rotating to drive across the field in front of the dock.
+    FindStream,
+    /// Driving towards the center beam of the dock.
+    GetStream,
+    /// Facing the dock.
+    Aligned,
+    /// Approaching the dock from afar.
+    AlignedFar,
+    /// Approaching the dock from nearby, for the final alignment.
+    AlignedNear,
+    /// Backing off after bumping into the dock without reaching the charging contacts.
+    BumpedDock,
+    /// On the charging contacts, waiting for the charger state to settle.
+    DockedIn,
+    /// Docked and charging.
+    Done,
+    Failed(DockingFailure),
+}
+
+/// Tuning of a [`DockingController`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DockingOptions {
+    /// Consecutive frames the charger must report docking before docking is done.
+    pub confirm_frames: u32,
+    /// Frames spent backing off after a bump.
+    pub backoff_frames: u32,
+    /// Docking attempts after a bump or a miss before giving up.
+    pub max_retries: u32,
+    /// Rotation in radians while scanning without any signal before giving up.
+    pub max_scan_rotation: f32,
+    /// Rotation in radians while turning to face across a field before starting over.
+    pub max_find_rotation: f32,
+    /// Distance in meters driven across a field before starting over.
+    pub max_stream_distance: f32,
+    /// Docking IR readings the [`DockRelation`] is smoothed over.
+    pub ir_window: usize,
+}
+
+impl Default for DockingOptions {
+    fn default() -> Self {
+        Self {
+            confirm_frames: 20,
+            backoff_frames: 25,
+            max_retries: 3,
+            max_scan_rotation: 1.6 * 2.0 * PI,
+            max_find_rotation: PI,
+            max_stream_distance: 1.5,
+            ir_window: 5,
+        }
+    }
+}
+
+/// Drives the base onto its docking station using the docking IR sensors.
+///
+/// Follows the state machine of the Kobuki auto-docking algorithm, driven by the smoothed
+/// [`DockRelation`] of a [`DockInterpreter`]. The base rotates until its center sensor sees
+/// the dock. If it sees one of the side fields, it turns, drives
+/// across the field into the center beam and scans again. Facing the center beam, it
+/// approaches while steering towards it. Docking is confirmed by the charger state. A bump
+/// without charging backs off and starts over, as does turning or driving across a field
+/// for longer than the [`DockingOptions`] allow.
+#[derive(Clone, Debug)]
+pub struct DockingController {
+    options: DockingOptions,
+    interpreter: DockInterpreter,
+    state: DockingState,
+    /// Side of the dock the base is on, negative on the left and positive on the right.
+    dock_detector: i32,
+    /// Rotation since scanning or finding the stream started, in radians.
+    rotated: f32,
+    /// Distance driven since getting the stream started, in meters.
+    travelled: f32,
+    last_pose: Option<Pose>,
+    /// Frames spent in the current docked or bumped state.
+    frames: u32,
+    retries: u32,
+}
+
+impl Default for DockingController {
+    fn default() -> Self {
+        Self::new(DockingOptions::default())
+    }
+}
+
+impl DockingController {
+    pub fn new(options: DockingOptions) -> Self {
+        Self {
+            options,
+            interpreter: DockInterpreter::new(options.ir_window),
+            state: DockingState::Idle,
+            dock_detector: 0,
+            rotated: 0.0,
+            travelled: 0.0,
+            last_pose: None,
+            frames: 0,
+            retries: 0,
+        }
+    }
+
+    pub fn state(&self) -> DockingState {
+        self.state
+    }
+
+    /// Where the dock was, as of the last frame.
+    pub fn relation(&self) -> Option<DockRelation> {
+        self.interpreter.relation()
+    }
+
+    /// Docking attempts restarted after a bump or a miss.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Returns the command for a feedback frame, or `None` once docking is done or failed.
+    ///
+    /// The pose is only used to measure the rotation and the distance driven in each state.
+    pub fn update(
+        &mut self,
+        ir: &DockingIr,
+        bsd: &BasicSensorData,
+        pose: &Pose,
+    ) -> Option<BaseControl> {
+        let (rotation, distance) = self.last_pose.replace(*pose).map_or((0.0, 0.0), |last| {
+            (
+                normalize_angle(pose.theta - last.theta),
+                pose.distance_to(&last),
+            )
+        });
+        let twist = self.step(ir, bsd, rotation, distance)?;
+        Some(twist.to_base_control())
+    }
+
+    fn step(
+        &mut self,
+        ir: &DockingIr,
+        bsd: &BasicSensorData,
+        rotation: f32,
+        distance: f32,
+    ) -> Option<Twist> {
+        if matches!(self.state, DockingState::Done | DockingState::Failed(_)) {
+            return None;
+        }
+        let relation = self.interpreter.update(ir);
+        if matches!(
+            bsd.charger,
+            Charger::DockingCharged | Charger::DockingCharging
+        ) {
+            return self.docked_in();
+        }
+        if self.state == DockingState::DockedIn {
+            // Slid off the contacts, so push on
+            self.state = DockingState::AlignedNear;
+        }
+        if !bsd.bumper.is_empty() && self.state != DockingState::BumpedDock {
+            self.state = DockingState::BumpedDock;
+            self.frames = 0;
+        }
+        let twist = match self.state {
+            DockingState::Idle => {
+                self.scan_again();
+                Twist::zero()
+            }
+            DockingState::Scan => self.scan(relation, rotation)?,
+            DockingState::FindStream => self.find_stream(relation, rotation)?,
+            DockingState::GetStream => self.get_stream(relation, distance)?,
+            DockingState::Aligned | DockingState::AlignedFar | DockingState::AlignedNear => {
+                self.aligned(relation)
+            }
+            DockingState::BumpedDock => self.bumped()?,
+            DockingState::DockedIn | DockingState::Done | DockingState::Failed(_) => {
+                unreachable!()
+            }
+        };
+        Some(twist)
+    }
+
+    fn docked_in(&mut self) -> Option<Twist> {
+        if self.state != DockingState::DockedIn {
+            self.state = DockingState::DockedIn;
+            self.frames = 0;
+        }
+        self.frames += 1;
+        if self.frames >= self.options.confirm_frames {
+            self.state = DockingState::Done;
+            return None;
+        }
+        Some(Twist::zero())
+    }
+
+    /// Rotates until the center sensor sees the dock.
+    fn scan(&mut self, relation: Option<DockRelation>, rotation: f32) -> Option<Twist> {
+        self.rotated += rotation.abs();
+        let twist = match relation {
+            Some(relation) if relation.bearing_sector.is_ahead() => match relation.region {
+                DockRegion::Center => {
+                    self.state = DockingState::Aligned;
+                    Twist::new(0.05, 0.0)
+                }
+                DockRegion::LeftField => {
+                    self.dock_detector -= 1;
+                    self.find_stream_start()
+                }
+                DockRegion::RightField => {
+                    self.dock_detector += 1;
+                    self.find_stream_start()
+                }
+            },
+            _ if self.rotated > self.options.max_scan_rotation => {
+                self.state = DockingState::Failed(DockingFailure::NotFound);
+                return None;
+            }
+            _ => Twist::new(0.0, 0.66),
+        };
+        Some(twist)
+    }
+
+    fn find_stream_start(&mut self) -> Twist {
+        self.state = DockingState::FindStream;
+        self.rotated = 0.0;
+        Twist::zero()
+    }
+
+    /// Turns until a side sensor sees the field the base is in, facing across it.
+    fn find_stream(&mut self, relation: Option<DockRelation>, rotation: f32) -> Option<Twist> {
+        self.rotated += rotation.abs();
+        if self.rotated > self.options.max_find_rotation {
+            return self.retry();
+        }
+        let sees = |region, bearing_sector| {
+            relation.is_some_and(|relation| {
+                relation.region == region && relation.bearing_sector == bearing_sector
+            })
+        };
+        let twist = if self.dock_detector > 0 {
+            // On the right of the dock, so turn clockwise until only the left sensor sees it
+            if sees(DockRegion::RightField, BearingSector::Left) {
+                self.get_stream_start()
+            } else {
+                Twist::new(0.0, -0.33)
+            }
+        } else if sees(DockRegion::LeftField, BearingSector::Right) {
+            self.get_stream_start()
+        } else {
+            Twist::new(0.0, 0.33)
+        };
+        Some(twist)
+    }
+
+    fn get_stream_start(&mut self) -> Twist {
+        self.state = DockingState::GetStream;
+        self.rotated = 0.0;
+        self.travelled = 0.0;
+        Twist::new(0.05, 0.0)
+    }
+
+    /// Drives across the field until the base leaves it.
+    ///
+    /// Losing the signal for the whole smoothing window is a miss, and starts over.
+    fn get_stream(&mut self, relation: Option<DockRelation>, distance: f32) -> Option<Twist> {
+        self.travelled += distance;
+        if self.travelled > self.options.max_stream_distance {
+            return self.retry();
+        }
+        let start = if self.dock_detector > 0 {
+            DockRegion::RightField
+        } else {
+            DockRegion::LeftField
+        };
+        match relation {
+            None => self.retry(),
+            Some(relation) if relation.region != start => {
+                self.scan_again();
+                Some(Twist::new(0.0, 0.1))
+            }
+            Some(_) => Some(Twist::new(0.05, 0.0)),
+        }
+    }
+
+    /// Approaches the dock, steering towards the center beam.
+    fn aligned(&mut self, relation: Option<DockRelation>) -> Twist {
+        let Some(relation) = relation.filter(|relation| relation.bearing_sector.is_ahead()) else {
+            // Lost the dock
+            self.scan_again();
+            return Twist::zero();
+        };
+        let (linear, turn) = match relation.distance {
+            DockDistance::Near => {
+                self.state = DockingState::AlignedNear;
+                (0.05, 0.1)
+            }
+            DockDistance::Far => {
+                self.state = DockingState::AlignedFar;
+                (0.1, 0.3)
+            }
+        };
+        let angular = match relation.region {
+            DockRegion::LeftField => turn,
+            DockRegion::Center => 0.0,
+            DockRegion::RightField => -turn,
+        };
+        Twist::new(linear, angular)
+    }
+
+    /// Backs off after a bump, then starts over.
+    fn bumped(&mut self) -> Option<Twist> {
+        self.frames += 1;
+        if self.frames < self.options.backoff_frames {
+            return Some(Twist::new(-0.05, 0.0));
+        }
+        self.retry()
+    }
+
+    /// Starts over with a new attempt, or gives up once the retries are used up.
+    fn retry(&mut self) -> Option<Twist> {
+        self.retries += 1;
+        if self.retries > self.options.max_retries {
+            self.state = DockingState::Failed(DockingFailure::TooManyRetries);
+            return None;
+        }
+        self.scan_again();
+        Some(Twist::zero())
+    }
+
+    fn scan_again(&mut self) {
+        self.interpreter.reset();
+        self.state = DockingState::Scan;
+        self.dock_detector = 0;
+        self.rotated = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rx::{IrSignal, SidesCentral};
+
+    fn ir(left: IrSignal, center: IrSignal, right: IrSignal) -> DockingIr {
+        DockingIr {
+            right,
+            center,
+            left,
+        }
+    }
+
+    fn none() -> DockingIr {
+        DockingIr::default()
+    }
+
+    /// A controller reacting to every reading on its own.
+    fn unsmoothed() -> DockingController {
+        DockingController::new(DockingOptions {
+            ir_window: 1,
+            ..Default::default()
+        })
+    }
+
+    fn twist(cmd: Option<BaseControl>) -> Twist {
+        Twist::from(cmd.unwrap())
+    }
+
+    #[test]
+    fn test_scan_not_found() {
+        let mut controller = DockingController::default();
+        let bsd = BasicSensorData::default();
+        let mut pose = Pose::default();
+        assert_eq!(
+            twist(controller.update(&none(), &bsd, &pose)),
+            Twist::zero()
+        );
+        assert_eq!(controller.state(), DockingState::Scan);
+        for _ in 0..100 {
+            let Some(cmd) = controller.update(&none(), &bsd, &pose) else {
+                break;
+            };
+            assert!(Twist::from(cmd).angular > 0.0);
+            pose.integrate(0.0, 0.2);
+        }
+        assert_eq!(
+            controller.state(),
+            DockingState::Failed(DockingFailure::NotFound)
+        );
+        assert_eq!(controller.update(&none(), &bsd, &pose), None);
+    }
+
+    #[test]
+    fn test_left_side_approach() {
+        let mut controller = unsmoothed();
+        let bsd = BasicSensorData::default();
+        let pose = Pose::default();
+        controller.update(&none(), &bsd, &pose);
+        // The front sensor sees the left field, so turn counter-clockwise to cross it
+        controller.update(
+            &ir(IrSignal::empty(), IrSignal::FAR_LEFT, IrSignal::empty()),
+            &bsd,
+            &pose,
+        );
+        assert_eq!(controller.state(), DockingState::FindStream);
+        let cmd = twist(controller.update(&none(), &bsd, &pose));
+        assert!(cmd.angular > 0.0);
+        // Until the right sensor sees it, then drive across
+        let right_sees_left = ir(IrSignal::empty(), IrSignal::empty(), IrSignal::FAR_LEFT);
+        let cmd = twist(controller.update(&right_sees_left, &bsd, &pose));
+        assert_eq!(controller.state(), DockingState::GetStream);
+        assert!(cmd.linear > 0.0 && cmd.angular == 0.0);
+        // Until the right sensor reaches the center beam, then scan again
+        let right_sees_right = ir(IrSignal::empty(), IrSignal::empty(), IrSignal::FAR_RIGHT);
+        controller.update(&right_sees_right, &bsd, &pose);
+        assert_eq!(controller.state(), DockingState::Scan);
+        let facing = ir(IrSignal::empty(), IrSignal::FAR_CENTER, IrSignal::empty());
+        controller.update(&facing, &bsd, &pose);
+        assert_eq!(controller.state(), DockingState::Aligned);
+    }
+
+    /// Brings a controller into [`DockingState::FindStream`] on the left of the dock.
+    fn find_stream_on_the_left(options: DockingOptions) -> DockingController {
+        let mut controller = DockingController::new(options);
+        let bsd = BasicSensorData::default();
+        controller.update(&none(), &bsd, &Pose::default());
+        controller.update(
+            &ir(IrSignal::empty(), IrSignal::FAR_LEFT, IrSignal::empty()),
+            &bsd,
+            &Pose::default(),
+        );
+        assert_eq!(controller.state(), DockingState::FindStream);
+        controller
+    }
+
+    #[test]
+    fn test_find_stream_budget() {
+        let mut controller = find_stream_on_the_left(DockingOptions {
+            max_retries: 1,
+            ir_window: 1,
+            ..Default::default()
+        });
+        let bsd = BasicSensorData::default();
+        let mut pose = Pose::default();
+        // The side sensor never sees the field, so give up turning after half a turn
+        while controller.state() == DockingState::FindStream {
+            pose.integrate(0.0, 0.2);
+            assert!(pose.theta.abs() < 4.0, "{:?}", pose);
+            controller.update(&none(), &bsd, &pose);
+        }
+        assert_eq!(controller.state(), DockingState::Scan);
+        assert_eq!(controller.retries(), 1);
+    }
+
+    #[test]
+    fn test_get_stream_budget() {
+        let mut controller = find_stream_on_the_left(DockingOptions {
+            max_retries: 0,
+            max_stream_distance: 0.52,
+            ir_window: 1,
+            ..Default::default()
+        });
+        let bsd = BasicSensorData::default();
+        let mut pose = Pose::default();
+        let right_sees_left = ir(IrSignal::empty(), IrSignal::empty(), IrSignal::FAR_LEFT);
+        controller.update(&right_sees_left, &bsd, &pose);
+        assert_eq!(controller.state(), DockingState::GetStream);
+        // The base never reaches the center beam, so give up driving after the budget
+        let mut frames = 0;
+        while controller.update(&right_sees_left, &bsd, &pose).is_some() {
+            assert_eq!(controller.state(), DockingState::GetStream);
+            pose.integrate(0.05, 0.0);
+            frames += 1;
+        }
+        assert_eq!(frames, 11);
+        assert_eq!(
+            controller.state(),
+            DockingState::Failed(DockingFailure::TooManyRetries)
+        );
+    }
+
+    #[test]
+    fn test_get_stream_signal_lost() {
+        let mut controller = find_stream_on_the_left(DockingOptions {
+            ir_window: 3,
+            ..Default::default()
+        });
+        let bsd = BasicSensorData::default();
+        let pose = Pose::default();
+        let right_sees_left = ir(IrSignal::empty(), IrSignal::empty(), IrSignal::FAR_LEFT);
+        // Once the front sensor reading has left the smoothing window
+        for _ in 0..3 {
+            controller.update(&right_sees_left, &bsd, &pose);
+        }
+        assert_eq!(controller.state(), DockingState::GetStream);
+        // A dropout within the smoothing window keeps driving across the field
+        for _ in 0..2 {
+            assert!(twist(controller.update(&none(), &bsd, &pose)).linear > 0.0);
+            assert_eq!(controller.state(), DockingState::GetStream);
+        }
+        // Without any signal for the whole window, the stream was missed
+        controller.update(&none(), &bsd, &pose);
+        assert_eq!(controller.state(), DockingState::Scan);
+        assert_eq!(controller.retries(), 1);
+    }
+
+    #[test]
+    fn test_aligned_steering() {
+        let mut controller = unsmoothed();
+        let bsd = BasicSensorData::default();
+        let pose = Pose::default();
+        controller.update(&none(), &bsd, &pose);
+        let facing = ir(IrSignal::empty(), IrSignal::FAR_CENTER, IrSignal::empty());
+        controller.update(&facing, &bsd, &pose);
+        let cmd = twist(controller.update(&facing, &bsd, &pose));
+        assert_eq!(controller.state(), DockingState::AlignedFar);
+        assert!((cmd.linear - 0.1).abs() < 1e-3 && cmd.angular == 0.0);
+
+        let far_left = ir(IrSignal::empty(), IrSignal::FAR_LEFT, IrSignal::empty());
+        assert!(twist(controller.update(&far_left, &bsd, &pose)).angular > 0.0);
+        // Near signals take precedence, as the far ones are still seen up close
+        let near_right = ir(
+            IrSignal::empty(),
+            IrSignal::NEAR_RIGHT | IrSignal::FAR_CENTER,
+            IrSignal::empty(),
+        );
+        let cmd = twist(controller.update(&near_right, &bsd, &pose));
+        assert_eq!(controller.state(), DockingState::AlignedNear);
+        assert!(cmd.angular < 0.0);
+
+        controller.update(&none(), &bsd, &pose);
+        assert_eq!(controller.state(), DockingState::Scan);
+    }
+
+    #[test]
+    fn test_dropouts_held() {
+        let mut controller = DockingController::default();
+        let bsd = BasicSensorData::default();
+        let pose = Pose::default();
+        controller.update(&none(), &bsd, &pose);
+        let facing = ir(IrSignal::empty(), IrSignal::FAR_CENTER, IrSignal::empty());
+        controller.update(&facing, &bsd, &pose);
+        // Flickering signals do not send the base back to scanning
+        for i in 0..20 {
+            let reading = if i % 3 == 0 { facing.clone() } else { none() };
+            assert!(twist(controller.update(&reading, &bsd, &pose)).linear > 0.0);
+            assert_eq!(controller.state(), DockingState::AlignedFar);
+        }
+    }
+
+    #[test]
+    fn test_docked() {
+        let mut controller = DockingController::new(DockingOptions {
+            confirm_frames: 3,
+            ..Default::default()
+        });
+        let pose = Pose::default();
+        let mut bsd = BasicSensorData::default();
+        controller.update(&none(), &bsd, &pose);
+        bsd.charger = Charger::DockingCharging;
+        // Bumping into the dock while charging does not back off
+        bsd.bumper = SidesCentral::CENTRAL;
+        for _ in 0..2 {
+            assert_eq!(
+                twist(controller.update(&none(), &bsd, &pose)),
+                Twist::zero()
+            );
+            assert_eq!(controller.state(), DockingState::DockedIn);
+        }
+        assert_eq!(controller.update(&none(), &bsd, &pose), None);
+        assert_eq!(controller.state(), DockingState::Done);
+    }
+
+    #[test]
+    fn test_bump_retries() {
+        let mut controller = DockingController::new(DockingOptions {
+            backoff_frames: 2,
+            max_retries: 1,
+            ..Default::default()
+        });
+        let pose = Pose::default();
+        let bumped = BasicSensorData {
+            bumper: SidesCentral::LEFT,
+            ..Default::default()
+        };
+        let clear = BasicSensorData::default();
+        controller.update(&none(), &clear, &pose);
+
+        assert!(twist(controller.update(&none(), &bumped, &pose)).linear < 0.0);
+        assert_eq!(controller.state(), DockingState::BumpedDock);
+        controller.update(&none(), &clear, &pose);
+        assert_eq!(controller.state(), DockingState::Scan);
+        assert_eq!(controller.retries(), 1);
+
+        controller.update(&none(), &bumped, &pose);
+        assert_eq!(controller.update(&none(), &clear, &pose), None);
+        assert_eq!(
+            controller.state(),
+            DockingState::Failed(DockingFailure::TooManyRetries)
+        );
+    }
+}
@@ -0,0 +1,235 @@
+use crate::rx::{DockingIr, IrSignal};
+use std::collections::VecDeque;
+
+const LEFT: IrSignal = IrSignal::NEAR_LEFT.union(IrSignal::FAR_LEFT);
+const RIGHT: IrSignal = IrSignal::NEAR_RIGHT.union(IrSignal::FAR_RIGHT);
+const NEAR: IrSignal = IrSignal::NEAR_LEFT
+    .union(IrSignal::NEAR_CENTER)
+    .union(IrSignal::NEAR_RIGHT);
+
+/// Which field of the dock the base is in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DockRegion {
+    /// The wide field on the left of the dock, as seen from the dock.
+    LeftField,
+    /// The narrow beam straight in front of the dock.
+    Center,
+    /// The wide field on the right of the dock, as seen from the dock.
+    RightField,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DockDistance {
+    Near,
+    Far,
+}
+
+/// Direction of the dock relative to the base, from the sensors that see it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BearingSector {
+    /// Only the left sensor sees the dock.
+    Left,
+    /// The left and center sensors see the dock.
+    FrontLeft,
+    /// The center sensor, or all sensors, see the dock.
+    Ahead,
+    /// The center and right sensors see the dock.
+    FrontRight,
+    /// Only the right sensor sees the dock.
+    Right,
+}
+
+impl BearingSector {
+    /// Returns true if the center sensor sees the dock.
+    pub fn is_ahead(&self) -> bool {
+        matches!(self, Self::FrontLeft | Self::Ahead | Self::FrontRight)
+    }
+}
+
+/// Where the base is relative to the dock, according to the docking IR sensors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DockRelation {
+    pub region: DockRegion,
+    pub distance: DockDistance,
+    pub bearing_sector: BearingSector,
+}
+
+impl DockRelation {
+    /// Interprets a single reading, or returns `None` if no sensor sees the dock.
+    ///
+    /// The region combines the fields seen by every sensor. Near signals take precedence,
+    /// since the far signals are also seen close to the dock. Seeing both side fields at
+    /// once means the base is in front of the dock. The center beam is seen over a wide
+    /// angle, so it only decides the region when no side field is seen.
+    pub fn from_ir(ir: &DockingIr) -> Option<Self> {
+        let bearing_sector = match (
+            !ir.left.is_empty(),
+            !ir.center.is_empty(),
+            !ir.right.is_empty(),
+        ) {
+            (false, false, false) => return None,
+            (true, false, false) => BearingSector::Left,
+            (true, true, false) => BearingSector::FrontLeft,
+            (false, true, true) => BearingSector::FrontRight,
+            (false, false, true) => BearingSector::Right,
+            _ => BearingSector::Ahead,
+        };
+        let signals = ir.left | ir.center | ir.right;
+        let (signals, distance) = if signals.intersects(NEAR) {
+            (signals & NEAR, DockDistance::Near)
+        } else {
+            (signals, DockDistance::Far)
+        };
+        let region = match (signals.intersects(LEFT), signals.intersects(RIGHT)) {
+            (true, false) => DockRegion::LeftField,
+            (false, true) => DockRegion::RightField,
+            // Both side fields, or only the center beam
+            _ => DockRegion::Center,
+        };
+        Some(Self {
+            region,
+            distance,
+            bearing_sector,
+        })
+    }
+}
+
+/// Turns docking IR readings into a [`DockRelation`], smoothed over the last few frames.
+///
+/// Like the Kobuki auto-docking algorithm, each sensor is taken to see every signal it saw
+/// within the window, so a signal flickering on and off is held.
+#[derive(Clone, Debug)]
+pub struct DockInterpreter {
+    window: usize,
+    history: VecDeque<DockingIr>,
+    relation: Option<DockRelation>,
+}
+
+impl Default for DockInterpreter {
+    fn default() -> Self {
+        Self::new(5)
+    }
+}
+
+impl DockInterpreter {
+    /// Smooths over `window` readings. A window of 1 disables smoothing.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            history: VecDeque::new(),
+            relation: None,
+        }
+    }
+
+    /// The relation as of the last reading.
+    pub fn relation(&self) -> Option<DockRelation> {
+        self.relation
+    }
+
+    /// Adds a reading, and returns the smoothed relation.
+    pub fn update(&mut self, ir: &DockingIr) -> Option<DockRelation> {
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back(ir.clone());
+        let combined = self
+            .history
+            .iter()
+            .fold(DockingIr::default(), |combined, ir| DockingIr {
+                right: combined.right | ir.right,
+                center: combined.center | ir.center,
+                left: combined.left | ir.left,
+            });
+        self.relation = DockRelation::from_ir(&combined);
+        self.relation
+    }
+
+    /// Forgets the previous readings.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.relation = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ir(left: IrSignal, center: IrSignal, right: IrSignal) -> DockingIr {
+        DockingIr {
+            right,
+            center,
+            left,
+        }
+    }
+
+    #[test]
+    fn test_from_ir() {
+        let none = IrSignal::empty();
+        assert_eq!(DockRelation::from_ir(&DockingIr::default()), None);
+        assert_eq!(
+            DockRelation::from_ir(&ir(IrSignal::FAR_RIGHT, none, none)),
+            Some(DockRelation {
+                region: DockRegion::RightField,
+                distance: DockDistance::Far,
+                bearing_sector: BearingSector::Left,
+            })
+        );
+        // Both side fields at once are seen in front of the dock
+        let relation =
+            DockRelation::from_ir(&ir(none, IrSignal::FAR_LEFT | IrSignal::FAR_RIGHT, none))
+                .unwrap();
+        assert_eq!(relation.region, DockRegion::Center);
+        assert_eq!(relation.bearing_sector, BearingSector::Ahead);
+        // Near signals take precedence over the far ones
+        let relation = DockRelation::from_ir(&ir(
+            none,
+            IrSignal::NEAR_LEFT | IrSignal::FAR_CENTER,
+            IrSignal::FAR_CENTER,
+        ))
+        .unwrap();
+        assert_eq!(relation.region, DockRegion::LeftField);
+        assert_eq!(relation.distance, DockDistance::Near);
+        assert_eq!(relation.bearing_sector, BearingSector::FrontRight);
+        assert!(relation.bearing_sector.is_ahead());
+        // The center beam alone is in front of the dock, but not next to a side field
+        let relation = DockRelation::from_ir(&ir(none, IrSignal::FAR_CENTER, none)).unwrap();
+        assert_eq!(relation.region, DockRegion::Center);
+        let relation = DockRelation::from_ir(&ir(
+            IrSignal::FAR_CENTER,
+            IrSignal::FAR_CENTER | IrSignal::FAR_RIGHT,
+            none,
+        ))
+        .unwrap();
+        assert_eq!(relation.region, DockRegion::RightField);
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let mut interpreter = DockInterpreter::new(3);
+        let none = IrSignal::empty();
+        let far_left = ir(none, IrSignal::FAR_LEFT, none);
+        let far_right = ir(none, IrSignal::FAR_RIGHT, none);
+        assert_eq!(
+            interpreter.update(&far_left).unwrap().region,
+            DockRegion::LeftField
+        );
+        // A dropout is held for the window
+        interpreter.update(&DockingIr::default());
+        assert_eq!(
+            interpreter.update(&DockingIr::default()).unwrap().region,
+            DockRegion::LeftField
+        );
+        assert_eq!(interpreter.update(&DockingIr::default()), None);
+        // Flickering between the side fields is in front of the dock
+        interpreter.update(&far_left);
+        let relation = interpreter.update(&far_right).unwrap();
+        assert_eq!(relation.region, DockRegion::Center);
+        assert_eq!(interpreter.relation(), Some(relation));
+        interpreter.reset();
+        assert_eq!(
+            interpreter.update(&far_right).unwrap().region,
+            DockRegion::RightField
+        );
+    }
+}
@@ -0,0 +1,5 @@
+mod controller;
+mod interpreter;
+
+pub use controller::{DockingController, DockingFailure, DockingOptions, DockingState};
+pub use interpreter::{BearingSector, DockDistance, DockInterpreter, DockRegion, DockRelation};
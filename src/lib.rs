@@ -1,3 +1,12 @@
+pub mod blocking;
+pub mod control;
+pub mod docking;
+pub mod navigation;
+pub mod odometry;
+pub mod robot;
 pub mod rx;
 pub mod serial_port;
 pub mod tx;
+
+#[cfg(test)]
+mod test_support;
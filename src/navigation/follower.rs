@@ -0,0 +1,161 @@
+use super::{PathProgress, PurePursuit};
+use crate::{
+    odometry::Odometry,
+    robot::{MAX_ANGULAR_VELOCITY, MAX_LINEAR_VELOCITY, MotionError, StopGuard},
+    rx::RobotState,
+    serial_port::{EmergencyStop, VelocitySource},
+};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    sync::{mpsc, watch},
+    time::timeout,
+};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Time without an odometry update after which following is aborted.
+const ODOMETRY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Progress of a [`PathFollower`].
+#[derive(Debug)]
+pub enum PathEvent {
+    /// The base moved along the path.
+    Progress(PathProgress),
+    /// The last waypoint was reached, and the base stopped.
+    Completed,
+    /// Following was aborted, and the base stopped.
+    Aborted(MotionError),
+}
+
+/// A [`Stream`] of the progress of a path being followed, see
+/// [`crate::robot::Robot::follow_path`].
+///
+/// The stream ends after [`PathEvent::Completed`] or [`PathEvent::Aborted`], which is also
+/// sent when [`crate::robot::Robot::stop`] is called. Progress events are dropped if the
+/// stream is not polled often enough. Dropping the stream stops the base.
+pub struct PathFollower {
+    events: ReceiverStream<PathEvent>,
+    _cancel: DropGuard,
+}
+
+impl PathFollower {
+    pub(crate) fn spawn(
+        pursuit: PurePursuit,
+        source: VelocitySource,
+        estop: watch::Receiver<EmergencyStop>,
+        odometry: watch::Receiver<Odometry>,
+        robot_state: watch::Receiver<RobotState>,
+        stopped: CancellationToken,
+    ) -> Self {
+        let (events_tx, events_rx) = mpsc::channel(16);
+        let stopped_tx = events_tx.clone();
+        let cancel = CancellationToken::new();
+        let task = follow(pursuit, source, estop, odometry, robot_state, events_tx);
+        let task = async move {
+            if stopped.run_until_cancelled(task).await.is_none() {
+                // Dropping the task stopped the base
+                let event = PathEvent::Aborted(MotionError::Stopped);
+                stopped_tx.send(event).await.ok(); // the stream may be gone - ignore errors
+            }
+        };
+        let token = cancel.clone();
+        tokio::spawn(async move { token.run_until_cancelled(task).await });
+        Self {
+            events: ReceiverStream::new(events_rx),
+            _cancel: cancel.drop_guard(),
+        }
+    }
+
+    /// A follower that aborts right away, without moving the base.
+    pub(crate) fn aborted(error: MotionError) -> Self {
+        let (events_tx, events_rx) = mpsc::channel(1);
+        events_tx.try_send(PathEvent::Aborted(error)).ok(); // the channel is empty
+        Self {
+            events: ReceiverStream::new(events_rx),
+            _cancel: CancellationToken::new().drop_guard(),
+        }
+    }
+}
+
+impl Stream for PathFollower {
+    type Item = PathEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+async fn follow(
+    mut pursuit: PurePursuit,
+    source: VelocitySource,
+    estop: watch::Receiver<EmergencyStop>,
+    mut odometry: watch::Receiver<Odometry>,
+    robot_state: watch::Receiver<RobotState>,
+    events: mpsc::Sender<PathEvent>,
+) {
+    let guard = StopGuard(&source);
+    let event = loop {
+        if let Err(error) = check(&estop, &robot_state) {
+            break PathEvent::Aborted(error);
+        }
+        let pose = odometry.borrow_and_update().pose();
+        let Some(twist) = pursuit.step(&pose) else {
+            break PathEvent::Completed;
+        };
+        let cmd = twist
+            .clamp(MAX_LINEAR_VELOCITY, MAX_ANGULAR_VELOCITY)
+            .to_base_control();
+        if let Err(error) = source.set_velocity(cmd.speed(), cmd.radius()) {
+            break PathEvent::Aborted(error.into());
+        }
+        // Progress is best effort, so a slow consumer does not hold up the control loop
+        events
+            .try_send(PathEvent::Progress(pursuit.progress()))
+            .ok();
+        match timeout(ODOMETRY_TIMEOUT, odometry.changed()).await {
+            Ok(Ok(())) => {}
+            _ => break PathEvent::Aborted(MotionError::Disconnected),
+        }
+    };
+    drop(guard);
+    events.send(event).await.ok(); // the stream was dropped - ignore errors
+}
+
+/// Fails if the motion has to be aborted.
+fn check(
+    estop: &watch::Receiver<EmergencyStop>,
+    robot_state: &watch::Receiver<RobotState>,
+) -> Result<(), MotionError> {
+    if estop.borrow().is_engaged() {
+        return Err(MotionError::EmergencyStop);
+    }
+    match robot_state
+        .borrow()
+        .basic_sensor_data
+        .as_ref()
+        .and_then(|bsd| MotionError::from_sensors(&bsd.value, true))
+    {
+        Some(hazard) => Err(hazard),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn test_aborted() {
+        let mut follower = PathFollower::aborted(MotionError::DrivesBackwards);
+        assert!(matches!(
+            follower.next().await,
+            Some(PathEvent::Aborted(MotionError::DrivesBackwards))
+        ));
+        assert!(follower.next().await.is_none());
+    }
+}
@@ -0,0 +1,9 @@
+mod follower;
+mod pure_pursuit;
+mod recorder;
+mod trajectory;
+
+pub use follower::{PathEvent, PathFollower};
+pub use pure_pursuit::{PathProgress, PurePursuit, PursuitOptions};
+pub use recorder::TrajectoryRecorder;
+pub use trajectory::{Trajectory, TrajectorySample};
@@ -0,0 +1,249 @@
+use crate::{control::Twist, odometry::Pose};
+
+/// Slowest linear velocity used when approaching the goal, in m/s.
+const MIN_SPEED: f32 = 0.05;
+
+/// How a [`PurePursuit`] follows its path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PursuitOptions {
+    /// Distance along the path to the point the base steers towards, in meters.
+    pub lookahead: f32,
+    /// Distance to the last waypoint at which the path is completed, in meters. Only checked
+    /// on the last segment, so a route ending where it starts is driven all the way round.
+    pub goal_tolerance: f32,
+    /// Highest linear velocity, in m/s.
+    pub max_linear: f32,
+    /// Highest angular velocity, in rad/s.
+    pub max_angular: f32,
+}
+
+impl Default for PursuitOptions {
+    fn default() -> Self {
+        Self {
+            lookahead: 0.3,
+            goal_tolerance: 0.05,
+            max_linear: 0.3,
+            max_angular: 1.0,
+        }
+    }
+}
+
+/// How far along its path a [`PurePursuit`] is.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PathProgress {
+    /// Index of the waypoint being approached.
+    pub waypoint: usize,
+    /// Distance left along the path to the last waypoint, in meters.
+    pub remaining: f32,
+    /// Distance from the base to the path, in meters.
+    pub cross_track_error: f32,
+}
+
+/// Follows a path of waypoints with the pure pursuit algorithm.
+///
+/// The path starts at the position of the base on the first step. At each step, the base
+/// steers along the arc leading to the point one lookahead distance further along the path,
+/// and slows down when approaching the last waypoint. If that point is behind the base, it
+/// turns on the spot instead.
+#[derive(Clone, Debug)]
+pub struct PurePursuit {
+    options: PursuitOptions,
+    /// The start position followed by the waypoints, once started.
+    points: Vec<[f32; 2]>,
+    started: bool,
+    /// Index of the first point of the segment being followed.
+    segment: usize,
+    progress: PathProgress,
+    done: bool,
+}
+
+impl PurePursuit {
+    /// Creates a follower for waypoints `(x, y)` in meters, in the odometry frame.
+    pub fn new(waypoints: impl IntoIterator<Item = (f32, f32)>, options: PursuitOptions) -> Self {
+        Self {
+            options,
+            points: waypoints.into_iter().map(|(x, y)| [x, y]).collect(),
+            started: false,
+            segment: 0,
+            progress: PathProgress::default(),
+            done: false,
+        }
+    }
+
+    /// Progress as of the last step.
+    pub fn progress(&self) -> PathProgress {
+        self.progress
+    }
+
+    /// Returns true once the last waypoint has been reached.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Returns the velocity to follow the path from `pose`, or `None` once the last waypoint
+    /// has been reached.
+    pub fn step(&mut self, pose: &Pose) -> Option<Twist> {
+        let position = [pose.x, pose.y];
+        if !self.started {
+            self.points.insert(0, position);
+            self.started = true;
+        }
+        if self.points.len() < 2 {
+            self.done = true;
+            return None;
+        }
+        while self.segment + 2 < self.points.len() && self.project(position).1 >= 1.0 {
+            self.segment += 1;
+        }
+        let (projection, _) = self.project(position);
+        self.progress = PathProgress {
+            waypoint: self.segment,
+            remaining: self.remaining(projection),
+            cross_track_error: distance(position, projection),
+        };
+        let last_segment = self.segment + 2 == self.points.len();
+        let goal = self.points[self.points.len() - 1];
+        if self.done || (last_segment && distance(position, goal) <= self.options.goal_tolerance) {
+            self.done = true;
+            return None;
+        }
+
+        let target = self.lookahead_point(projection);
+        let (dx, dy) = (target[0] - pose.x, target[1] - pose.y);
+        let (sin, cos) = pose.theta.sin_cos();
+        let (x, y) = (cos * dx + sin * dy, cos * dy - sin * dx);
+        if x <= 0.0 {
+            return Some(Twist::new(0.0, self.options.max_angular.copysign(y)));
+        }
+        let remaining = self.progress.remaining;
+        let linear = (self.options.max_linear * (remaining / self.options.lookahead).min(1.0))
+            .max(MIN_SPEED.min(self.options.max_linear));
+        let angular = linear * 2.0 * y / (x * x + y * y);
+        let scale = (self.options.max_angular / angular.abs()).min(1.0);
+        Some(Twist::new(linear * scale, angular * scale))
+    }
+
+    /// Returns the closest point of the current segment, and how far along it is from 0 to 1.
+    fn project(&self, position: [f32; 2]) -> ([f32; 2], f32) {
+        let (start, end) = (self.points[self.segment], self.points[self.segment + 1]);
+        let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+        let length_squared = dx * dx + dy * dy;
+        if length_squared == 0.0 {
+            return (end, 1.0);
+        }
+        let t = ((position[0] - start[0]) * dx + (position[1] - start[1]) * dy) / length_squared;
+        let clamped = t.clamp(0.0, 1.0);
+        ([start[0] + clamped * dx, start[1] + clamped * dy], t)
+    }
+
+    /// Returns the point one lookahead distance along the path from `projection`.
+    fn lookahead_point(&self, projection: [f32; 2]) -> [f32; 2] {
+        let mut left = self.options.lookahead;
+        let mut from = projection;
+        for &to in &self.points[self.segment + 1..] {
+            let length = distance(from, to);
+            if length >= left {
+                let t = left / length;
+                return [
+                    from[0] + t * (to[0] - from[0]),
+                    from[1] + t * (to[1] - from[1]),
+                ];
+            }
+            left -= length;
+            from = to;
+        }
+        from
+    }
+
+    fn remaining(&self, projection: [f32; 2]) -> f32 {
+        let rest = &self.points[self.segment + 1..];
+        distance(projection, rest[0])
+            + rest
+                .windows(2)
+                .map(|pair| distance(pair[0], pair[1]))
+                .sum::<f32>()
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (b[0] - a[0]).hypot(b[1] - a[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// Drives a simulated base along the path, and returns the final pose and the largest
+    /// cross-track error.
+    fn simulate(pursuit: &mut PurePursuit, mut pose: Pose) -> (Pose, f32) {
+        let options = pursuit.options;
+        let mut max_error: f32 = 0.0;
+        for _ in 0..5000 {
+            let Some(twist) = pursuit.step(&pose) else {
+                return (pose, max_error);
+            };
+            assert!(twist.linear >= 0.0 && twist.linear <= options.max_linear);
+            assert!(twist.angular.abs() <= options.max_angular + 1e-6);
+            max_error = max_error.max(pursuit.progress().cross_track_error);
+            pose.integrate(twist.linear * 0.02, twist.angular * 0.02);
+        }
+        panic!("goal not reached, ended at {:?}", pose);
+    }
+
+    #[test]
+    fn test_straight() {
+        let mut pursuit = PurePursuit::new([(1.0, 0.0)], PursuitOptions::default());
+        pursuit.step(&Pose::default());
+        assert_eq!(pursuit.progress().remaining, 1.0);
+        let (pose, max_error) = simulate(&mut pursuit, Pose::default());
+        assert!(pose.distance_to(&Pose::new(1.0, 0.0, 0.0)) <= 0.05);
+        assert!(max_error < 1e-6);
+        assert!(pursuit.is_done());
+        assert_eq!(pursuit.step(&pose), None);
+    }
+
+    #[test]
+    fn test_corner() {
+        let waypoints = [(1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let mut pursuit = PurePursuit::new(waypoints, PursuitOptions::default());
+        let (pose, max_error) = simulate(&mut pursuit, Pose::default());
+        assert!(pose.distance_to(&Pose::new(0.0, 1.0, 0.0)) <= 0.05);
+        // Corners are cut by less than the lookahead distance
+        assert!(max_error < 0.2, "{}", max_error);
+        let progress = pursuit.progress();
+        assert_eq!(progress.waypoint, 2);
+        assert!(progress.remaining <= 0.05);
+    }
+
+    #[test]
+    fn test_closed_route() {
+        // The route ends where the base starts, which must not complete it right away
+        let waypoints = [(1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+        let mut pursuit = PurePursuit::new(waypoints, PursuitOptions::default());
+        let twist = pursuit.step(&Pose::default()).unwrap();
+        assert_eq!(twist.linear, PursuitOptions::default().max_linear);
+        assert_eq!(pursuit.progress().remaining, 4.0);
+        let (pose, max_error) = simulate(&mut pursuit, Pose::default());
+        assert!(pose.distance_to(&Pose::default()) <= 0.05);
+        assert!(max_error < 0.2, "{}", max_error);
+        assert_eq!(pursuit.progress().waypoint, 3);
+    }
+
+    #[test]
+    fn test_goal_behind() {
+        let mut pursuit = PurePursuit::new([(-1.0, 0.0)], PursuitOptions::default());
+        let twist = pursuit.step(&Pose::new(0.0, 0.0, 0.1)).unwrap();
+        // Turns towards the goal, the shortest way
+        assert_eq!(twist, Twist::new(0.0, 1.0));
+        let (pose, _) = simulate(&mut pursuit, Pose::new(0.0, 0.0, 0.1));
+        assert!((pose.theta.abs() - PI).abs() < 0.2, "{:?}", pose);
+    }
+
+    #[test]
+    fn test_empty_path() {
+        let mut pursuit = PurePursuit::new([], PursuitOptions::default());
+        assert_eq!(pursuit.step(&Pose::default()), None);
+        assert!(pursuit.is_done());
+    }
+}
@@ -0,0 +1,73 @@
+use super::{Trajectory, TrajectorySample};
+use crate::{control::Twist, odometry::Odometry, serial_port::CommandSender};
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Records the pose of the base while it is driven, see
+/// [`crate::robot::Robot::record_trajectory`].
+pub struct TrajectoryRecorder {
+    task: JoinHandle<Trajectory>,
+    /// Stops recording when the recorder is dropped.
+    stop: DropGuard,
+}
+
+impl TrajectoryRecorder {
+    pub(crate) fn spawn(
+        min_distance: f32,
+        min_rotation: f32,
+        sender: CommandSender,
+        odometry: watch::Receiver<Odometry>,
+    ) -> Self {
+        let stop = CancellationToken::new();
+        let task = tokio::spawn(record(
+            min_distance,
+            min_rotation,
+            sender,
+            odometry,
+            stop.clone(),
+        ));
+        Self {
+            task,
+            stop: stop.drop_guard(),
+        }
+    }
+
+    /// Stops recording, and returns the recorded trajectory.
+    pub async fn finish(self) -> Trajectory {
+        drop(self.stop);
+        self.task.await.unwrap_or_default()
+    }
+}
+
+/// Records a sample for every odometry update, until stopped or the odometry ends.
+async fn record(
+    min_distance: f32,
+    min_rotation: f32,
+    sender: CommandSender,
+    mut odometry: watch::Receiver<Odometry>,
+    stop: CancellationToken,
+) -> Trajectory {
+    let start = Instant::now();
+    let mut trajectory = Trajectory::new();
+    loop {
+        let pose = odometry.borrow_and_update().pose();
+        let command = sender
+            .commanded_velocity()
+            .map_or(Twist::zero(), Twist::from);
+        let sample = TrajectorySample {
+            time: start.elapsed(),
+            pose,
+            command,
+        };
+        trajectory.record(sample, min_distance, min_rotation);
+        tokio::select! {
+            _ = stop.cancelled() => break,
+            changed = odometry.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    trajectory
+}
@@ -0,0 +1,295 @@
+use crate::{
+    control::Twist,
+    odometry::{Pose, normalize_angle},
+};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::Path,
+    time::Duration,
+};
+
+const CSV_HEADER: &str = "time_ms,x,y,theta,linear,angular";
+
+/// A pose of a recorded trajectory, with the velocity commanded at the time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrajectorySample {
+    /// Time since the start of the recording.
+    pub time: Duration,
+    pub pose: Pose,
+    /// Velocity commanded at the time. It is kept for reference, and not replayed.
+    pub command: Twist,
+}
+
+/// A route driven by the base, as recorded from the odometry.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trajectory {
+    samples: Vec<TrajectorySample>,
+}
+
+impl Trajectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn samples(&self) -> &[TrajectorySample] {
+        &self.samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Appends a sample, unless the base moved less than `min_distance` meters and turned
+    /// less than `min_rotation` radians since the last one. Returns true if the sample was
+    /// appended.
+    pub fn record(
+        &mut self,
+        sample: TrajectorySample,
+        min_distance: f32,
+        min_rotation: f32,
+    ) -> bool {
+        if let Some(last) = self.samples.last()
+            && last.pose.distance_to(&sample.pose) < min_distance
+            && normalize_angle(sample.pose.theta - last.pose.theta).abs() < min_rotation
+        {
+            return false;
+        }
+        self.samples.push(sample);
+        true
+    }
+
+    /// Returns true if any sample was recorded while the base was commanded backwards.
+    pub fn drives_backwards(&self) -> bool {
+        self.samples
+            .iter()
+            .any(|sample| sample.command.linear < 0.0)
+    }
+
+    /// Moves and rotates the whole trajectory, so it starts at `start`.
+    ///
+    /// Replaying the trajectory from a different starting pose then follows the same route
+    /// relative to the base.
+    pub fn aligned_to(&self, start: Pose) -> Self {
+        let Some(first) = self.samples.first() else {
+            return Self::new();
+        };
+        let rotation = start.theta - first.pose.theta;
+        let (sin, cos) = rotation.sin_cos();
+        let samples = self
+            .samples
+            .iter()
+            .map(|sample| {
+                let (dx, dy) = (sample.pose.x - first.pose.x, sample.pose.y - first.pose.y);
+                TrajectorySample {
+                    pose: Pose {
+                        x: start.x + cos * dx - sin * dy,
+                        y: start.y + sin * dx + cos * dy,
+                        theta: normalize_angle(sample.pose.theta + rotation),
+                    },
+                    ..*sample
+                }
+            })
+            .collect();
+        Self { samples }
+    }
+
+    /// The positions of the trajectory, as waypoints for [`super::PurePursuit`].
+    pub fn waypoints(&self) -> Vec<(f32, f32)> {
+        self.samples
+            .iter()
+            .map(|sample| (sample.pose.x, sample.pose.y))
+            .collect()
+    }
+
+    /// Writes the trajectory as CSV, with a header line.
+    pub fn write_csv(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "{}", CSV_HEADER)?;
+        for sample in &self.samples {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{}",
+                sample.time.as_millis(),
+                sample.pose.x,
+                sample.pose.y,
+                sample.pose.theta,
+                sample.command.linear,
+                sample.command.angular
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reads a trajectory written by [`Self::write_csv`].
+    ///
+    /// Fails with [`ErrorKind::InvalidData`] if the header or a line is malformed.
+    pub fn read_csv(reader: impl BufRead) -> std::io::Result<Self> {
+        let mut lines = reader.lines();
+        if lines.next().transpose()?.as_deref() != Some(CSV_HEADER) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "missing trajectory header",
+            ));
+        }
+        let mut samples = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let sample = parse_sample(&line).ok_or_else(|| {
+                std::io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("malformed trajectory line {}: {}", i + 2, line),
+                )
+            })?;
+            samples.push(sample);
+        }
+        Ok(Self { samples })
+    }
+
+    /// Saves the trajectory to a CSV file.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_csv(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Loads a trajectory from a CSV file.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::read_csv(BufReader::new(File::open(path)?))
+    }
+}
+
+fn parse_sample(line: &str) -> Option<TrajectorySample> {
+    let mut fields = line.split(',').map(str::trim);
+    let time = Duration::from_millis(fields.next()?.parse().ok()?);
+    let mut next = || fields.next()?.parse::<f32>().ok();
+    let sample = TrajectorySample {
+        time,
+        pose: Pose::new(next()?, next()?, next()?),
+        command: Twist::new(next()?, next()?),
+    };
+    fields.next().is_none().then_some(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navigation::{PurePursuit, PursuitOptions};
+    use std::f32::consts::PI;
+
+    fn sample(ms: u64, x: f32, y: f32, theta: f32) -> TrajectorySample {
+        TrajectorySample {
+            time: Duration::from_millis(ms),
+            pose: Pose::new(x, y, theta),
+            command: Twist::new(0.2, 0.1),
+        }
+    }
+
+    #[test]
+    fn test_record_spacing() {
+        let mut trajectory = Trajectory::new();
+        assert!(trajectory.record(sample(0, 0.0, 0.0, 0.0), 0.1, 0.2));
+        assert!(!trajectory.record(sample(20, 0.05, 0.0, 0.1), 0.1, 0.2));
+        assert!(trajectory.record(sample(40, 0.1, 0.0, 0.1), 0.1, 0.2));
+        assert_eq!(trajectory.waypoints(), vec![(0.0, 0.0), (0.1, 0.0)]);
+    }
+
+    #[test]
+    fn test_record_turn_on_the_spot() {
+        let mut trajectory = Trajectory::new();
+        trajectory.record(sample(0, 0.0, 0.0, 3.0), 0.1, 0.2);
+        // Across ±π, the base turned by less than the spacing
+        assert!(!trajectory.record(sample(20, 0.0, 0.0, -3.2), 0.1, 0.2));
+        assert!(trajectory.record(sample(40, 0.0, 0.0, -3.0), 0.1, 0.2));
+        assert_eq!(trajectory.samples().len(), 2);
+    }
+
+    #[test]
+    fn test_drives_backwards() {
+        let mut trajectory = Trajectory::new();
+        trajectory.record(sample(0, 0.0, 0.0, 0.0), 0.0, 0.0);
+        assert!(!trajectory.drives_backwards());
+        trajectory.record(
+            TrajectorySample {
+                command: Twist::new(-0.1, 0.0),
+                ..sample(100, -0.1, 0.0, 0.0)
+            },
+            0.0,
+            0.0,
+        );
+        assert!(trajectory.drives_backwards());
+    }
+
+    #[test]
+    fn test_aligned_to() {
+        let mut trajectory = Trajectory::new();
+        trajectory.record(sample(0, 1.0, 1.0, PI / 2.0), 0.0, 0.0);
+        trajectory.record(sample(100, 1.0, 2.0, PI / 2.0), 0.0, 0.0);
+        // Started facing along x from the origin instead
+        let aligned = trajectory.aligned_to(Pose::default());
+        let end = aligned.samples()[1].pose;
+        assert!(
+            end.distance_to(&Pose::new(1.0, 0.0, 0.0)) < 1e-6,
+            "{:?}",
+            end
+        );
+        assert!(end.theta.abs() < 1e-6);
+        assert_eq!(aligned.samples()[1].time, Duration::from_millis(100));
+        assert!(Trajectory::new().aligned_to(Pose::default()).is_empty());
+    }
+
+    #[test]
+    fn test_replay_loop() {
+        // A square driven counter-clockwise, back to where the recording started
+        let mut trajectory = Trajectory::new();
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+        for (i, pair) in corners.windows(2).enumerate() {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            for step in 0..10 {
+                let t = step as f32 / 10.0;
+                let ms = (i * 10 + step) as u64 * 500;
+                let pose = Pose::new(x0 + t * (x1 - x0), y0 + t * (y1 - y0), i as f32 * PI / 2.0);
+                trajectory.record(sample(ms, pose.x, pose.y, pose.theta), 0.05, 0.2);
+            }
+        }
+        trajectory.record(sample(20000, 0.0, 0.0, 0.0), 0.05, 0.2);
+
+        // Replayed from its start, the whole loop is driven before stopping
+        let mut pursuit = PurePursuit::new(trajectory.waypoints(), PursuitOptions::default());
+        let mut pose = Pose::default();
+        let mut farthest: f32 = 0.0;
+        for _ in 0..5000 {
+            let Some(twist) = pursuit.step(&pose) else {
+                break;
+            };
+            pose.integrate(twist.linear * 0.02, twist.angular * 0.02);
+            farthest = farthest.max(pose.distance_to(&Pose::default()));
+        }
+        assert!(pursuit.is_done());
+        assert!(pose.distance_to(&Pose::default()) <= 0.05, "{:?}", pose);
+        assert!(farthest > 1.3, "{}", farthest);
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let mut trajectory = Trajectory::new();
+        trajectory.record(sample(0, 0.0, 0.0, 0.0), 0.0, 0.0);
+        trajectory.record(sample(1234, 0.123, -4.5, 3.0), 0.0, 0.0);
+        let mut csv = Vec::new();
+        trajectory.write_csv(&mut csv).unwrap();
+        assert_eq!(Trajectory::read_csv(&csv[..]).unwrap(), trajectory);
+    }
+
+    #[test]
+    fn test_csv_errors() {
+        let error = Trajectory::read_csv(&b"0,0,0,0,0,0\n"[..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let csv = format!("{}\n0,0,0,0,0,0\n10,0,0,zero,0,0\n", CSV_HEADER);
+        let error = Trajectory::read_csv(csv.as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("line 3"), "{}", error);
+        let csv = format!("{}\n0,0,0,0,0,0,0\n", CSV_HEADER);
+        assert!(Trajectory::read_csv(csv.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use super::{GyroHeading, Pose, Unwrapper, WheelGeometry};
+use crate::{
+    control::Twist,
+    rx::{BasicSensorData, FEEDBACK_PERIOD, Feedback},
+};
+use std::time::Duration;
+
+/// Where the odometry takes the rotation of the base from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HeadingSource {
+    /// The difference between the wheel encoders.
+    #[default]
+    Encoders,
+    /// The gyro angle of the inertial sensor, blended with the encoders.
+    ///
+    /// A blend of 1.0 uses the gyro only, and a blend of 0.0 the encoders only.
+    Gyro { blend: f32 },
+}
+
+/// Dead reckoning from the wheel encoders.
+///
+/// The change in each encoder is converted into an arc travelled by the base, and the
+/// velocity is computed using the robot timestamps, so it is unaffected by serial latency.
+#[derive(Clone, Debug)]
+pub struct Odometry {
+    geometry: WheelGeometry,
+    heading_source: HeadingSource,
+    left: Unwrapper,
+    right: Unwrapper,
+    timestamp: Unwrapper,
+    gyro: GyroHeading,
+    /// Gyro rotation received since the last encoder update.
+    gyro_rotation: Option<f32>,
+    pose: Pose,
+    velocity: Twist,
+}
+
+impl Default for Odometry {
+    fn default() -> Self {
+        Self::new(WheelGeometry::default())
+    }
+}
+
+impl Odometry {
+    pub fn new(geometry: WheelGeometry) -> Self {
+        Self {
+            geometry,
+            heading_source: HeadingSource::Encoders,
+            left: Unwrapper::new(),
+            right: Unwrapper::new(),
+            timestamp: Unwrapper::new(),
+            gyro: GyroHeading::default(),
+            gyro_rotation: None,
+            pose: Pose::default(),
+            velocity: Twist::zero(),
+        }
+    }
+
+    /// Selects where the rotation of the base is taken from.
+    pub fn heading_source(mut self, source: HeadingSource) -> Self {
+        self.heading_source = match source {
+            HeadingSource::Gyro { blend } => HeadingSource::Gyro {
+                blend: blend.clamp(0.0, 1.0),
+            },
+            source => source,
+        };
+        self
+    }
+
+    pub fn geometry(&self) -> &WheelGeometry {
+        &self.geometry
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    /// Velocity of the base, measured over the last update.
+    pub fn velocity(&self) -> Twist {
+        self.velocity
+    }
+
+    /// Moves the odometry frame, so the base is at `pose`.
+    pub fn reset(&mut self, pose: Pose) {
+        self.pose = pose;
+        self.velocity = Twist::zero();
+    }
+
+    /// Updates the pose from a feedback frame. Returns true if the frame held encoder data.
+    ///
+    /// A timestamp going backwards is taken as a reboot of the robot, which also resets the
+    /// encoders. With the gyro as heading source, so is a jump in the gyro angle larger than
+    /// plausible for the elapsed time. The odometry then continues from the current pose.
+    pub fn update(&mut self, feedback: &Feedback) -> bool {
+        let elapsed = feedback
+            .basic_sensor_data
+            .as_ref()
+            .and_then(|bsd| self.timestamp.delta(bsd.timestamp));
+        let rebooted = elapsed.is_some_and(|elapsed| elapsed < 0);
+        if rebooted {
+            self.restart();
+        }
+        if let HeadingSource::Gyro { .. } = self.heading_source
+            && let Some(inertial) = &feedback.inertial_sensor
+        {
+            let had_reading = self.gyro.has_reading();
+            let elapsed = elapsed.map_or(FEEDBACK_PERIOD, |elapsed| {
+                Duration::from_millis(elapsed.max(0) as u64)
+            });
+            match self.gyro.update_after(inertial.angle, elapsed) {
+                Some(rotation) if !rebooted => {
+                    *self.gyro_rotation.get_or_insert(0.0) += rotation;
+                }
+                None if had_reading && !rebooted => self.restart(),
+                _ => {}
+            }
+        }
+        let Some(bsd) = &feedback.basic_sensor_data else {
+            return false;
+        };
+        self.update_sensors(bsd);
+        true
+    }
+
+    /// Updates the pose from the basic sensor data.
+    ///
+    /// Only the encoders are used, unless gyro data has been passed to [`Self::update`].
+    pub fn update_sensors(&mut self, bsd: &BasicSensorData) {
+        let (distance, encoder_rotation) = self.wheel_motion(bsd);
+        let rotation = match (self.heading_source, self.gyro_rotation.take()) {
+            (HeadingSource::Gyro { blend }, Some(gyro_rotation)) => {
+                blend * gyro_rotation + (1.0 - blend) * encoder_rotation
+            }
+            _ => encoder_rotation,
+        };
+        self.pose.integrate(distance, rotation);
+        self.update_velocity(bsd.timestamp, distance, rotation);
+    }
+
+    /// Returns the distance travelled and the rotation since the previous update, according
+    /// to the encoders.
+    fn wheel_motion(&mut self, bsd: &BasicSensorData) -> (f32, f32) {
+        let meters_per_tick = self.geometry.meters_per_tick();
+        let left = self.left.update(bsd.left_encoder) as f32 * meters_per_tick;
+        let right = self.right.update(bsd.right_encoder) as f32 * meters_per_tick;
+        (
+            (left + right) / 2.0,
+            (right - left) / self.geometry.wheelbase,
+        )
+    }
+
+    /// Forgets the previous readings, so the next frame only primes the odometry.
+    fn restart(&mut self) {
+        self.left.reset();
+        self.right.reset();
+        self.timestamp.reset();
+        self.gyro_rotation = None;
+        self.velocity = Twist::zero();
+    }
+
+    fn update_velocity(&mut self, timestamp: u16, distance: f32, rotation: f32) {
+        let elapsed = self.timestamp.update(timestamp);
+        // The timestamp has a resolution of 1 ms, so keep the last velocity if it did not move
+        if elapsed > 0 {
+            let dt = elapsed as f32 / 1000.0;
+            self.velocity = Twist::new(distance / dt, rotation / dt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{frame, inertial, sensors};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_straight_across_wraparound() {
+        let mut odometry = Odometry::default();
+        let ticks_per_meter = 1.0 / odometry.geometry().meters_per_tick();
+        let start = 65000;
+        odometry.update_sensors(&sensors(65500, start, start));
+        // Drive 0.5 m in 1 s, with both the encoders and the timestamp wrapping around
+        let ticks = (0.5 * ticks_per_meter).round() as u16;
+        for i in 1..=10 {
+            let encoder = start.wrapping_add(ticks * i / 10);
+            odometry.update_sensors(&sensors(65500u16.wrapping_add(100 * i), encoder, encoder));
+        }
+        let pose = odometry.pose();
+        assert!((pose.x - 0.5).abs() < 1e-3, "{:?}", pose);
+        assert!(pose.y.abs() < 1e-6 && pose.theta.abs() < 1e-6);
+        assert!((odometry.velocity().linear - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_spin_on_the_spot() {
+        let mut odometry = Odometry::default();
+        let geometry = *odometry.geometry();
+        // Each wheel travels a quarter of the circle around the center of the base
+        let ticks = (PI * geometry.wheelbase / 4.0 / geometry.meters_per_tick()).round() as u16;
+        odometry.update_sensors(&sensors(0, 5000, 5000));
+        odometry.update_sensors(&sensors(500, 5000 - ticks, 5000 + ticks));
+        let pose = odometry.pose();
+        assert!((pose.theta - PI / 2.0).abs() < 1e-3, "{:?}", pose);
+        assert!(pose.x.abs() < 1e-6 && pose.y.abs() < 1e-6);
+        assert!((odometry.velocity().angular - PI).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut odometry = Odometry::default();
+        odometry.update_sensors(&sensors(0, 0, 0));
+        odometry.reset(Pose::new(1.0, 2.0, 0.0));
+        odometry.update_sensors(&sensors(10, 100, 100));
+        assert!(odometry.pose().x > 1.0);
+        assert_eq!(odometry.pose().y, 2.0);
+    }
+
+    #[test]
+    fn test_gyro_heading() {
+        let mut odometry = Odometry::default().heading_source(HeadingSource::Gyro { blend: 1.0 });
+        let ticks_per_meter = 1.0 / odometry.geometry().meters_per_tick();
+        let ticks = (0.1 * ticks_per_meter).round() as u16;
+        // The encoders claim to drive straight, but the gyro turns across ±180°
+        odometry.update(&frame(sensors(0, 0, 0), Some(inertial(175.0, 0.0))));
+        for i in 1..=10u16 {
+            let angle = 175.0 + 1.0 * i as f32;
+            let angle = if angle > 180.0 { angle - 360.0 } else { angle };
+            odometry.update(&frame(
+                sensors(20 * i, ticks * i, ticks * i),
+                Some(inertial(angle, 0.0)),
+            ));
+        }
+        let pose = odometry.pose();
+        assert!(
+            (pose.theta - 10.0f32.to_radians()).abs() < 1e-3,
+            "{:?}",
+            pose
+        );
+        assert!((pose.distance_to(&Pose::default()) - 1.0).abs() < 1e-2);
+        assert!((odometry.velocity().angular - 1.0f32.to_radians() / 0.02).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_gyro_blend() {
+        let mut odometry = Odometry::default().heading_source(HeadingSource::Gyro { blend: 0.5 });
+        odometry.update(&frame(sensors(0, 5000, 5000), Some(inertial(0.0, 0.0))));
+        odometry.update(&frame(sensors(20, 5000, 5000), Some(inertial(10.0, 0.0))));
+        assert!((odometry.pose().theta.to_degrees() - 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_reboot() {
+        let mut odometry = Odometry::default().heading_source(HeadingSource::Gyro { blend: 1.0 });
+        odometry.update(&frame(
+            sensors(30000, 30000, 30000),
+            Some(inertial(120.0, 0.0)),
+        ));
+        odometry.update(&frame(
+            sensors(30020, 30100, 30100),
+            Some(inertial(120.0, 0.0)),
+        ));
+        let before = odometry.pose();
+        // The robot reboots, re-zeroing the gyro, the encoders and the timestamp
+        odometry.update(&frame(sensors(5, 0, 0), Some(inertial(0.0, 0.0))));
+        assert_eq!(odometry.pose(), before);
+        odometry.update(&frame(sensors(25, 100, 100), Some(inertial(0.0, 0.0))));
+        let pose = odometry.pose();
+        assert!(
+            (pose.distance_to(&before) - 100.0 * odometry.geometry().meters_per_tick()).abs()
+                < 1e-6
+        );
+        assert_eq!(pose.theta, before.theta);
+    }
+
+    #[test]
+    fn test_lagged_frames_are_not_a_reboot() {
+        let mut odometry = Odometry::default().heading_source(HeadingSource::Gyro { blend: 1.0 });
+        let ticks = (0.1 / odometry.geometry().meters_per_tick()).round() as u16;
+        odometry.update(&frame(sensors(1000, 0, 0), Some(inertial(0.0, 0.0))));
+        // Ten frames were dropped while driving an arc at 180°/s
+        odometry.update(&frame(
+            sensors(1200, ticks, ticks),
+            Some(inertial(36.0, 0.0)),
+        ));
+        let pose = odometry.pose();
+        assert!((pose.theta.to_degrees() - 36.0).abs() < 1e-3, "{:?}", pose);
+        assert!((pose.distance_to(&Pose::default()) - 0.1).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_reboot_without_gyro() {
+        let mut odometry = Odometry::default();
+        odometry.update(&frame(sensors(30000, 30000, 30000), None));
+        odometry.update(&frame(sensors(30020, 30100, 30100), None));
+        let before = odometry.pose();
+        // The encoders restart from zero, which would otherwise look like driving backwards
+        odometry.update(&frame(sensors(5, 0, 0), None));
+        assert_eq!(odometry.pose(), before);
+    }
+}
@@ -0,0 +1,428 @@
+use super::{GyroHeading, Pose, Unwrapper, WheelGeometry};
+use crate::{
+    control::Twist,
+    rx::{FEEDBACK_PERIOD, Feedback},
+};
+use std::time::Duration;
+
+const X: usize = 0;
+const Y: usize = 1;
+const THETA: usize = 2;
+const V: usize = 3;
+const OMEGA: usize = 4;
+const N: usize = 5;
+
+/// Variance of the velocities before the first measurement.
+const INITIAL_VELOCITY_VARIANCE: f32 = 1.0;
+
+type Matrix = [[f32; N]; N];
+
+/// Standard deviations of the process and the measurements of the [`Ekf`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EkfNoise {
+    /// Unmodelled change in linear velocity, in m/s².
+    pub linear_acceleration: f32,
+    /// Unmodelled change in angular velocity, in rad/s².
+    pub angular_acceleration: f32,
+    /// Linear velocity measured by the encoders, in m/s.
+    pub encoder_linear_velocity: f32,
+    /// Angular velocity measured by the encoders, in rad/s.
+    pub encoder_angular_velocity: f32,
+    /// Heading measured by the inertial sensor, in radians.
+    pub gyro_angle: f32,
+    /// Angular velocity measured by the inertial sensor, in rad/s.
+    pub gyro_angle_rate: f32,
+    /// Angular velocity measured by a single raw gyro sample, in rad/s.
+    pub raw_gyro_rate: f32,
+}
+
+impl Default for EkfNoise {
+    fn default() -> Self {
+        Self {
+            linear_acceleration: 2.0,
+            angular_acceleration: 6.0,
+            encoder_linear_velocity: 0.01,
+            encoder_angular_velocity: 0.1,
+            gyro_angle: 0.005,
+            gyro_angle_rate: 0.05,
+            raw_gyro_rate: 0.05,
+        }
+    }
+}
+
+/// Extended Kalman filter estimating the pose and velocity of the base.
+///
+/// The state holds the pose and the linear and angular velocity. On every frame with encoder
+/// data, the velocities are corrected by the encoders, the inertial sensor angle rate and the
+/// raw gyro samples, the pose is propagated using the robot timestamps, and the heading is
+/// corrected by the inertial sensor angle.
+#[derive(Clone, Debug)]
+pub struct Ekf {
+    geometry: WheelGeometry,
+    noise: EkfNoise,
+    state: [f32; N],
+    covariance: Matrix,
+    left: Unwrapper,
+    right: Unwrapper,
+    timestamp: Unwrapper,
+    gyro: GyroHeading,
+    /// Heading of the state when the gyro heading was zero.
+    gyro_offset: Option<f32>,
+}
+
+impl Default for Ekf {
+    fn default() -> Self {
+        Self::new(WheelGeometry::default(), EkfNoise::default())
+    }
+}
+
+impl Ekf {
+    pub fn new(geometry: WheelGeometry, noise: EkfNoise) -> Self {
+        let mut covariance = [[0.0; N]; N];
+        covariance[V][V] = INITIAL_VELOCITY_VARIANCE;
+        covariance[OMEGA][OMEGA] = INITIAL_VELOCITY_VARIANCE;
+        Self {
+            geometry,
+            noise,
+            state: [0.0; N],
+            covariance,
+            left: Unwrapper::new(),
+            right: Unwrapper::new(),
+            timestamp: Unwrapper::new(),
+            gyro: GyroHeading::default(),
+            gyro_offset: None,
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        Pose::new(self.state[X], self.state[Y], self.state[THETA])
+    }
+
+    pub fn velocity(&self) -> Twist {
+        Twist::new(self.state[V], self.state[OMEGA])
+    }
+
+    /// Covariance of the pose, in the order x, y, θ.
+    pub fn covariance(&self) -> [[f32; 3]; 3] {
+        std::array::from_fn(|row| std::array::from_fn(|col| self.covariance[row][col]))
+    }
+
+    /// Moves the odometry frame, so the base is at `pose` with no uncertainty.
+    pub fn reset(&mut self, pose: Pose) {
+        self.state[X] = pose.x;
+        self.state[Y] = pose.y;
+        self.state[THETA] = pose.theta;
+        for row in 0..N {
+            for col in 0..N {
+                if row <= THETA || col <= THETA {
+                    self.covariance[row][col] = 0.0;
+                }
+            }
+        }
+        self.gyro_offset = None;
+    }
+
+    /// Updates the estimate from a feedback frame. Returns true if the frame held encoder data.
+    pub fn update(&mut self, feedback: &Feedback) -> bool {
+        let elapsed = feedback
+            .basic_sensor_data
+            .as_ref()
+            .and_then(|bsd| self.timestamp.delta(bsd.timestamp));
+        if elapsed.is_some_and(|elapsed| elapsed < 0) {
+            self.restart();
+        }
+        let elapsed = elapsed.map_or(FEEDBACK_PERIOD, |elapsed| {
+            Duration::from_millis(elapsed.max(0) as u64)
+        });
+        let heading = feedback
+            .inertial_sensor
+            .as_ref()
+            .map(|inertial| self.gyro_heading(inertial.angle, elapsed));
+        let Some(bsd) = &feedback.basic_sensor_data else {
+            return false;
+        };
+
+        let meters_per_tick = self.geometry.meters_per_tick();
+        let left = self.left.update(bsd.left_encoder) as f32 * meters_per_tick;
+        let right = self.right.update(bsd.right_encoder) as f32 * meters_per_tick;
+        // The timestamp has a resolution of 1 ms, so never let the interval collapse to zero
+        let dt = self.timestamp.update(bsd.timestamp).max(1) as f32 / 1000.0;
+
+        self.predict_velocity(dt);
+        let noise = self.noise;
+        self.measure(
+            V,
+            (left + right) / 2.0 / dt,
+            noise.encoder_linear_velocity.powi(2),
+        );
+        self.measure(
+            OMEGA,
+            (right - left) / self.geometry.wheelbase / dt,
+            noise.encoder_angular_velocity.powi(2),
+        );
+        if let Some(inertial) = &feedback.inertial_sensor {
+            self.measure(
+                OMEGA,
+                inertial.angle_rate.to_radians(),
+                noise.gyro_angle_rate.powi(2),
+            );
+        }
+        if let Some(gyro) = &feedback.gyro
+            && let Some(yaw_rate) = gyro.yaw_rate()
+        {
+            let variance = noise.raw_gyro_rate.powi(2) / gyro.samples.len() as f32;
+            self.measure(OMEGA, yaw_rate, variance);
+        }
+        self.propagate(dt);
+        if let Some(heading) = heading {
+            self.measure(THETA, heading, noise.gyro_angle.powi(2));
+        }
+        true
+    }
+
+    /// Converts a gyro angle into a heading in the frame of the state.
+    ///
+    /// A jump in the gyro angle larger than plausible for the elapsed time is taken as a
+    /// reboot of the robot, which also resets the encoders and the timestamp.
+    fn gyro_heading(&mut self, angle: f32, elapsed: Duration) -> f32 {
+        let had_reading = self.gyro.has_reading();
+        if self.gyro.update_after(angle, elapsed).is_none() && had_reading {
+            self.restart();
+        }
+        let heading = self.gyro.heading();
+        *self.gyro_offset.get_or_insert(self.state[THETA] - heading) + heading
+    }
+
+    /// Forgets the previous readings after a reboot of the robot.
+    fn restart(&mut self) {
+        self.left.reset();
+        self.right.reset();
+        self.timestamp.reset();
+        self.gyro_offset = None;
+    }
+
+    /// Accounts for the velocities changing over `dt`.
+    fn predict_velocity(&mut self, dt: f32) {
+        self.covariance[V][V] += (self.noise.linear_acceleration * dt).powi(2);
+        self.covariance[OMEGA][OMEGA] += (self.noise.angular_acceleration * dt).powi(2);
+    }
+
+    /// Moves the pose by the velocities over `dt`.
+    fn propagate(&mut self, dt: f32) {
+        let [_, _, theta, v, omega] = self.state;
+        let (sin, cos) = (theta + omega * dt / 2.0).sin_cos();
+        self.state[X] += v * dt * cos;
+        self.state[Y] += v * dt * sin;
+        self.state[THETA] += omega * dt;
+
+        let mut jacobian = identity();
+        jacobian[X][THETA] = -v * dt * sin;
+        jacobian[X][V] = dt * cos;
+        jacobian[X][OMEGA] = -v * dt * sin * dt / 2.0;
+        jacobian[Y][THETA] = v * dt * cos;
+        jacobian[Y][V] = dt * sin;
+        jacobian[Y][OMEGA] = v * dt * cos * dt / 2.0;
+        jacobian[THETA][OMEGA] = dt;
+        let covariance = multiply(
+            &multiply(&jacobian, &self.covariance),
+            &transpose(&jacobian),
+        );
+        // Keep the covariance symmetric despite rounding errors
+        self.covariance = std::array::from_fn(|row| {
+            std::array::from_fn(|col| (covariance[row][col] + covariance[col][row]) / 2.0)
+        });
+    }
+
+    /// Corrects the state by a measurement of a single state variable.
+    fn measure(&mut self, index: usize, measurement: f32, variance: f32) {
+        let innovation = measurement - self.state[index];
+        let innovation_variance = self.covariance[index][index] + variance;
+        let gain: [f32; N] =
+            std::array::from_fn(|row| self.covariance[row][index] / innovation_variance);
+        let measured_row = self.covariance[index];
+        for (row, gain) in gain.into_iter().enumerate() {
+            self.state[row] += gain * innovation;
+            for (value, measured) in self.covariance[row].iter_mut().zip(measured_row) {
+                *value -= gain * measured;
+            }
+        }
+    }
+}
+
+fn identity() -> Matrix {
+    std::array::from_fn(|row| std::array::from_fn(|col| if row == col { 1.0 } else { 0.0 }))
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    std::array::from_fn(|row| {
+        std::array::from_fn(|col| (0..N).map(|i| a[row][i] * b[i][col]).sum())
+    })
+}
+
+fn transpose(a: &Matrix) -> Matrix {
+    std::array::from_fn(|row| std::array::from_fn(|col| a[col][row]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        odometry::Odometry,
+        rx::Gyro,
+        test_support::{self, frame, sensors},
+    };
+    use std::f32::consts::PI;
+
+    const PERIOD_MS: u16 = 20;
+
+    /// Generates the feedback of a base with the given wheel and gyro velocities.
+    struct Simulation {
+        geometry: WheelGeometry,
+        time: u16,
+        left: f32,
+        right: f32,
+        angle: f32,
+    }
+
+    impl Simulation {
+        fn new() -> Self {
+            Self {
+                geometry: WheelGeometry::default(),
+                time: 0,
+                left: 1000.0,
+                right: 1000.0,
+                angle: 0.0,
+            }
+        }
+
+        /// Advances one period, returning a frame with or without inertial data.
+        fn step(&mut self, left: f32, right: f32, yaw_rate: f32, inertial: bool) -> Feedback {
+            let dt = PERIOD_MS as f32 / 1000.0;
+            let meters_per_tick = self.geometry.meters_per_tick();
+            self.time = self.time.wrapping_add(PERIOD_MS);
+            self.left += left * dt / meters_per_tick;
+            self.right += right * dt / meters_per_tick;
+            self.angle += yaw_rate.to_degrees() * dt;
+            let angle = (self.angle + 180.0).rem_euclid(360.0) - 180.0;
+            let digits = (yaw_rate.to_degrees() / crate::rx::GYRO_DEGREES_PER_DIGIT) as i16;
+            let bsd = sensors(
+                self.time,
+                self.left.round() as i64 as u16,
+                self.right.round() as i64 as u16,
+            );
+            Feedback {
+                gyro: inertial.then(|| Gyro {
+                    frame_id: 0,
+                    samples: vec![[0, 0, digits]; 2],
+                }),
+                ..frame(
+                    bsd,
+                    inertial.then(|| test_support::inertial(angle, yaw_rate.to_degrees())),
+                )
+            }
+        }
+    }
+
+    fn assert_symmetric(covariance: &[[f32; 3]; 3]) {
+        let transposed: [[f32; 3]; 3] =
+            std::array::from_fn(|row| std::array::from_fn(|col| covariance[col][row]));
+        assert_eq!(*covariance, transposed);
+        assert!((0..3).all(|i| covariance[i][i] >= 0.0), "{:?}", covariance);
+    }
+
+    #[test]
+    fn test_straight() {
+        let mut ekf = Ekf::default();
+        let mut simulation = Simulation::new();
+        for _ in 0..=50 {
+            assert!(ekf.update(&simulation.step(0.5, 0.5, 0.0, true)));
+        }
+        let pose = ekf.pose();
+        assert!((pose.x - 0.5).abs() < 0.02, "{:?}", pose);
+        assert!(pose.y.abs() < 1e-3 && pose.theta.abs() < 1e-3, "{:?}", pose);
+        assert!((ekf.velocity().linear - 0.5).abs() < 0.01);
+        let covariance = ekf.covariance();
+        assert_symmetric(&covariance);
+        assert!(covariance[X][X] > 0.0);
+    }
+
+    #[test]
+    fn test_turn_across_wraparound() {
+        let mut ekf = Ekf::default();
+        let mut simulation = Simulation::new();
+        let geometry = WheelGeometry::default();
+        // Drive a full circle with a radius of 0.5 m in 4 s
+        let angular = PI / 2.0;
+        let wheel = |offset: f32| angular * (0.5 + offset);
+        let (left, right) = (
+            wheel(-geometry.wheelbase / 2.0),
+            wheel(geometry.wheelbase / 2.0),
+        );
+        ekf.update(&simulation.step(0.0, 0.0, 0.0, true));
+        for _ in 0..200 {
+            ekf.update(&simulation.step(left, right, angular, true));
+        }
+        let pose = ekf.pose();
+        assert!(pose.distance_to(&Pose::default()) < 0.05, "{:?}", pose);
+        assert!(pose.theta.abs() < 0.02, "{:?}", pose);
+        assert!((ekf.velocity().angular - angular).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_gyro_overrides_wheel_slip() {
+        let mut ekf = Ekf::default();
+        let mut odometry = Odometry::default();
+        let mut simulation = Simulation::new();
+        // One wheel spins on a cable, so the encoders report a turn the gyro does not see
+        for _ in 0..50 {
+            let frame = simulation.step(0.0, 0.3, 0.0, true);
+            ekf.update(&frame);
+            odometry.update(&frame);
+        }
+        assert!(odometry.pose().theta.abs() > 1.0);
+        assert!(ekf.pose().theta.abs() < 0.02, "{:?}", ekf.pose());
+        assert!(ekf.velocity().angular.abs() < 0.1);
+    }
+
+    #[test]
+    fn test_heading_uncertainty_grows_without_gyro() {
+        let mut with_gyro = Ekf::default();
+        let mut without_gyro = Ekf::default();
+        let mut simulation = Simulation::new();
+        for _ in 0..100 {
+            let frame = simulation.step(0.2, 0.2, 0.0, true);
+            with_gyro.update(&frame);
+            without_gyro.update(&Feedback {
+                inertial_sensor: None,
+                gyro: None,
+                ..frame
+            });
+        }
+        let with_gyro = with_gyro.covariance()[THETA][THETA];
+        let without_gyro = without_gyro.covariance()[THETA][THETA];
+        assert!(with_gyro < 0.005f32.powi(2), "{}", with_gyro);
+        assert!(
+            without_gyro > 10.0 * with_gyro,
+            "{} {}",
+            without_gyro,
+            with_gyro
+        );
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut ekf = Ekf::default();
+        let mut simulation = Simulation::new();
+        for _ in 0..10 {
+            ekf.update(&simulation.step(0.2, 0.2, 0.0, true));
+        }
+        ekf.reset(Pose::new(1.0, 2.0, PI / 2.0));
+        assert_eq!(ekf.covariance(), [[0.0; 3]; 3]);
+        for _ in 0..10 {
+            ekf.update(&simulation.step(0.2, 0.2, 0.0, true));
+        }
+        let pose = ekf.pose();
+        assert!((pose.theta - PI / 2.0).abs() < 1e-3, "{:?}", pose);
+        assert!(pose.y > 2.0 && (pose.x - 1.0).abs() < 1e-3, "{:?}", pose);
+    }
+}
@@ -0,0 +1,52 @@
+/// Turns a wrapping 16 bit counter into the change since the previous reading.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Unwrapper {
+    last: Option<u16>,
+}
+
+impl Unwrapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the signed change since the previous reading, or 0 for the first reading.
+    ///
+    /// Changes of more than half the counter range between two readings are indistinguishable
+    /// from wrapping in the opposite direction.
+    pub fn update(&mut self, raw: u16) -> i16 {
+        let delta = self.last.map_or(0, |last| raw.wrapping_sub(last) as i16);
+        self.last = Some(raw);
+        delta
+    }
+
+    /// Returns the signed change from the previous reading to `raw` without taking it, or
+    /// `None` before the first reading.
+    pub fn delta(&self, raw: u16) -> Option<i16> {
+        self.last.map(|last| raw.wrapping_sub(last) as i16)
+    }
+
+    /// Forgets the previous reading.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap() {
+        let mut unwrapper = Unwrapper::new();
+        assert_eq!(unwrapper.update(65530), 0);
+        assert_eq!(unwrapper.update(65535), 5);
+        // Wraps around forwards and backwards
+        assert_eq!(unwrapper.update(4), 5);
+        assert_eq!(unwrapper.update(65534), -6);
+        assert_eq!(unwrapper.delta(65530), Some(-4));
+        assert_eq!(unwrapper.update(65535), 1);
+        unwrapper.reset();
+        assert_eq!(unwrapper.delta(100), None);
+        assert_eq!(unwrapper.update(100), 0);
+    }
+}
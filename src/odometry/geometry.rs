@@ -0,0 +1,45 @@
+use std::f32::consts::PI;
+
+/// Physical dimensions of the drive train, used to convert encoder ticks into motion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WheelGeometry {
+    /// Encoder ticks per wheel revolution.
+    pub ticks_per_revolution: f32,
+    /// Wheel radius in meters.
+    pub wheel_radius: f32,
+    /// Distance between the wheels in meters.
+    pub wheelbase: f32,
+}
+
+impl Default for WheelGeometry {
+    fn default() -> Self {
+        Self::KOBUKI
+    }
+}
+
+impl WheelGeometry {
+    /// The geometry of the Kobuki base.
+    pub const KOBUKI: Self = Self {
+        ticks_per_revolution: 2578.33,
+        wheel_radius: 0.035,
+        wheelbase: 0.23,
+    };
+
+    /// Distance travelled by a wheel per encoder tick, in meters.
+    pub fn meters_per_tick(&self) -> f32 {
+        2.0 * PI * self.wheel_radius / self.ticks_per_revolution
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meters_per_tick() {
+        let geometry = WheelGeometry::default();
+        let distance = geometry.meters_per_tick() * geometry.ticks_per_revolution;
+        assert!((distance - 2.0 * PI * 0.035).abs() < 1e-6);
+        assert!((geometry.meters_per_tick() - 0.0000853).abs() < 1e-7);
+    }
+}
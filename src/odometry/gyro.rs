@@ -0,0 +1,108 @@
+use super::normalize_angle;
+use crate::rx::FEEDBACK_PERIOD;
+use std::time::Duration;
+
+/// Largest change in the gyro angle per feedback period considered plausible, in degrees.
+///
+/// At the feedback rate of 50 Hz, this is far beyond what the base can rotate. Larger
+/// changes happen when the gyro angle is re-zeroed, e.g. when the robot boots.
+pub const DEFAULT_MAX_GYRO_JUMP: f32 = 30.0;
+
+/// Unwraps the gyro angle of the inertial sensor, which wraps around at ±180°.
+#[derive(Clone, Copy, Debug)]
+pub struct GyroHeading {
+    last: Option<f32>,
+    heading: f32,
+    max_jump: f32,
+}
+
+impl Default for GyroHeading {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_GYRO_JUMP)
+    }
+}
+
+impl GyroHeading {
+    pub fn new(max_jump: f32) -> Self {
+        Self {
+            last: None,
+            heading: 0.0,
+            max_jump,
+        }
+    }
+
+    /// Takes a gyro angle in degrees, and returns the rotation since the previous reading in
+    /// radians.
+    ///
+    /// Returns `None` for the first reading, and when the angle jumps because the gyro was
+    /// re-zeroed. The next reading continues from the new angle.
+    pub fn update(&mut self, angle: f32) -> Option<f32> {
+        self.update_after(angle, FEEDBACK_PERIOD)
+    }
+
+    /// Like [`Self::update`], for a reading taken `elapsed` after the previous one.
+    ///
+    /// The largest plausible jump grows with the number of feedback periods that elapsed,
+    /// so frames that were dropped in between are not mistaken for a re-zeroed gyro.
+    pub fn update_after(&mut self, angle: f32, elapsed: Duration) -> Option<f32> {
+        let last = self.last.replace(angle)?;
+        let rotation = normalize_angle((angle - last).to_radians());
+        let periods = (elapsed.as_secs_f32() / FEEDBACK_PERIOD.as_secs_f32()).max(1.0);
+        if rotation.to_degrees().abs() > self.max_jump * periods {
+            return None;
+        }
+        self.heading += rotation;
+        Some(rotation)
+    }
+
+    /// Returns true once an angle has been received.
+    pub fn has_reading(&self) -> bool {
+        self.last.is_some()
+    }
+
+    /// The accumulated heading in radians, continuous across ±180°.
+    pub fn heading(&self) -> f32 {
+        self.heading
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap() {
+        let mut gyro = GyroHeading::default();
+        assert_eq!(gyro.update(170.0), None);
+        let angles = [175.0, -179.0, -170.0, -179.0, 179.0];
+        let rotations: Vec<_> = angles
+            .into_iter()
+            .map(|angle| gyro.update(angle).unwrap().to_degrees())
+            .collect();
+        for (rotation, expected) in rotations.iter().zip([5.0, 6.0, 9.0, -9.0, -2.0]) {
+            assert!((rotation - expected).abs() < 1e-3, "{:?}", rotations);
+        }
+        assert!((gyro.heading().to_degrees() - 9.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_re_zeroed() {
+        let mut gyro = GyroHeading::default();
+        gyro.update(90.0);
+        gyro.update(92.0);
+        assert_eq!(gyro.update(0.0), None);
+        let rotation = gyro.update(1.0).unwrap();
+        assert!((rotation.to_degrees() - 1.0).abs() < 1e-3);
+        assert!((gyro.heading().to_degrees() - 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_jump_after_lag() {
+        let mut gyro = GyroHeading::default();
+        gyro.update(0.0);
+        // Ten frames were dropped while turning at 180°/s
+        let rotation = gyro.update_after(36.0, Duration::from_millis(200)).unwrap();
+        assert!((rotation.to_degrees() - 36.0).abs() < 1e-3);
+        assert_eq!(gyro.update_after(0.0, Duration::from_millis(20)), None);
+    }
+}
@@ -0,0 +1,17 @@
+mod diff_drive;
+mod ekf;
+mod encoder;
+mod geometry;
+mod gyro;
+mod pose;
+mod slip;
+mod wheel_velocity;
+
+pub use diff_drive::{HeadingSource, Odometry};
+pub use ekf::{Ekf, EkfNoise};
+pub use encoder::Unwrapper;
+pub use geometry::WheelGeometry;
+pub use gyro::{DEFAULT_MAX_GYRO_JUMP, GyroHeading};
+pub use pose::{Pose, normalize_angle};
+pub use slip::{SlipCause, SlipDetector, SlipEvent, SlipThresholds};
+pub use wheel_velocity::{WheelVelocities, WheelVelocityEstimator};
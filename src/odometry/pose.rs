@@ -0,0 +1,70 @@
+use std::f32::consts::PI;
+
+/// Position and heading of the base in the odometry frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pose {
+    /// Position in meters.
+    pub x: f32,
+    pub y: f32,
+    /// Counter-clockwise heading in radians, within (-π, π].
+    pub theta: f32,
+}
+
+impl Pose {
+    pub fn new(x: f32, y: f32, theta: f32) -> Self {
+        Self {
+            x,
+            y,
+            theta: normalize_angle(theta),
+        }
+    }
+
+    /// Moves the pose by `distance` along an arc turning by `rotation`.
+    pub fn integrate(&mut self, distance: f32, rotation: f32) {
+        let heading = self.theta + rotation / 2.0;
+        self.x += distance * heading.cos();
+        self.y += distance * heading.sin();
+        self.theta = normalize_angle(self.theta + rotation);
+    }
+
+    /// Straight-line distance to `other` in meters.
+    pub fn distance_to(&self, other: &Pose) -> f32 {
+        (other.x - self.x).hypot(other.y - self.y)
+    }
+}
+
+/// Wraps an angle in radians into (-π, π].
+pub fn normalize_angle(angle: f32) -> f32 {
+    let angle = angle.rem_euclid(2.0 * PI);
+    if angle > PI { angle - 2.0 * PI } else { angle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_angle() {
+        assert!((normalize_angle(3.0 * PI / 2.0) + PI / 2.0).abs() < 1e-6);
+        assert!((normalize_angle(-3.0 * PI / 2.0) - PI / 2.0).abs() < 1e-6);
+        assert!((normalize_angle(PI) - PI).abs() < 1e-6);
+        assert_eq!(normalize_angle(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_integrate() {
+        let mut pose = Pose::new(1.0, 0.0, PI / 2.0);
+        pose.integrate(2.0, 0.0);
+        assert!((pose.x - 1.0).abs() < 1e-5);
+        assert!((pose.y - 2.0).abs() < 1e-5);
+
+        // A quarter circle with a radius of 1 m
+        let mut pose = Pose::default();
+        for _ in 0..100 {
+            pose.integrate(PI / 2.0 / 100.0, PI / 2.0 / 100.0);
+        }
+        assert!((pose.x - 1.0).abs() < 1e-4);
+        assert!((pose.y - 1.0).abs() < 1e-4);
+        assert!((pose.theta - PI / 2.0).abs() < 1e-4);
+    }
+}
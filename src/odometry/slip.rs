@@ -0,0 +1,278 @@
+use super::{Unwrapper, WheelGeometry};
+use crate::rx::{BasicSensorData, Feedback, Sides};
+
+/// When the [`SlipDetector`] considers the odometry unreliable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SlipThresholds {
+    /// Largest accepted difference between the yaw rate of the encoders and the gyro, in rad/s.
+    pub yaw_rate_tolerance: f32,
+    /// Smallest PWM magnitude at which a wheel is expected to turn.
+    pub stall_pwm: u8,
+    /// Number of consecutive frames a condition must hold before it is reported or cleared.
+    pub frames: u32,
+}
+
+impl Default for SlipThresholds {
+    fn default() -> Self {
+        Self {
+            yaw_rate_tolerance: 0.3,
+            stall_pwm: 40,
+            frames: 5,
+        }
+    }
+}
+
+/// Why the odometry is considered unreliable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlipCause {
+    /// The encoders and the gyro disagree on the yaw rate, in rad/s.
+    YawRateMismatch { encoders: f32, gyro: f32 },
+    /// The wheels are driven, but the encoders do not move.
+    WheelStalled(Sides),
+}
+
+impl std::fmt::Display for SlipCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::YawRateMismatch { encoders, gyro } => write!(
+                f,
+                "yaw rate mismatch (encoders {:.2} rad/s, gyro {:.2} rad/s)",
+                encoders, gyro
+            ),
+            Self::WheelStalled(sides) => write!(f, "wheel stalled ({})", sides),
+        }
+    }
+}
+
+/// A change in the reliability of the odometry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlipEvent {
+    /// The wheels no longer reflect the motion of the base.
+    SlipDetected(SlipCause),
+    /// The wheels and the gyro agree again.
+    SlipCleared,
+    /// Both wheels dropped, so the base was lifted off the ground.
+    Kidnapped,
+    /// The base is back on the ground, possibly somewhere else.
+    PutDown,
+}
+
+/// Detects when the wheel odometry goes wrong, because a wheel slips or the base is picked up.
+#[derive(Clone, Debug)]
+pub struct SlipDetector {
+    geometry: WheelGeometry,
+    thresholds: SlipThresholds,
+    left: Unwrapper,
+    right: Unwrapper,
+    timestamp: Unwrapper,
+    /// Consecutive frames the current slip state has been contradicted.
+    contradicting: u32,
+    slipping: bool,
+    kidnapped: bool,
+}
+
+impl Default for SlipDetector {
+    fn default() -> Self {
+        Self::new(WheelGeometry::default(), SlipThresholds::default())
+    }
+}
+
+impl SlipDetector {
+    pub fn new(geometry: WheelGeometry, thresholds: SlipThresholds) -> Self {
+        Self {
+            geometry,
+            thresholds,
+            left: Unwrapper::new(),
+            right: Unwrapper::new(),
+            timestamp: Unwrapper::new(),
+            contradicting: 0,
+            slipping: false,
+            kidnapped: false,
+        }
+    }
+
+    pub fn is_slipping(&self) -> bool {
+        self.slipping
+    }
+
+    pub fn is_kidnapped(&self) -> bool {
+        self.kidnapped
+    }
+
+    /// Checks a feedback frame, and returns the resulting changes.
+    ///
+    /// The yaw rates are only compared for frames holding inertial sensor data.
+    pub fn update(&mut self, feedback: &Feedback) -> Vec<SlipEvent> {
+        let mut events = Vec::new();
+        let Some(bsd) = &feedback.basic_sensor_data else {
+            return events;
+        };
+        let left = self.left.update(bsd.left_encoder);
+        let right = self.right.update(bsd.right_encoder);
+        let elapsed = self.timestamp.update(bsd.timestamp);
+
+        let lifted = bsd.wheel_drop == Sides::all();
+        if lifted != self.kidnapped {
+            self.kidnapped = lifted;
+            events.push(if lifted {
+                SlipEvent::Kidnapped
+            } else {
+                SlipEvent::PutDown
+            });
+            if lifted && self.slipping {
+                events.push(SlipEvent::SlipCleared);
+            }
+            self.slipping = false;
+            self.contradicting = 0;
+        }
+        if self.kidnapped || elapsed <= 0 {
+            return events;
+        }
+
+        let meters_per_tick = self.geometry.meters_per_tick();
+        let encoder_yaw_rate = (right - left) as f32 * meters_per_tick
+            / self.geometry.wheelbase
+            / (elapsed as f32 / 1000.0);
+        let cause = self.stall(bsd, left, right).or_else(|| {
+            let gyro = feedback.inertial_sensor.as_ref()?.angle_rate.to_radians();
+            ((encoder_yaw_rate - gyro).abs() > self.thresholds.yaw_rate_tolerance).then_some(
+                SlipCause::YawRateMismatch {
+                    encoders: encoder_yaw_rate,
+                    gyro,
+                },
+            )
+        });
+
+        if cause.is_some() == self.slipping {
+            self.contradicting = 0;
+            return events;
+        }
+        self.contradicting += 1;
+        if self.contradicting >= self.thresholds.frames {
+            self.contradicting = 0;
+            self.slipping = cause.is_some();
+            events.push(match cause {
+                Some(cause) => SlipEvent::SlipDetected(cause),
+                None => SlipEvent::SlipCleared,
+            });
+        }
+        events
+    }
+
+    fn stall(&self, bsd: &BasicSensorData, left: i16, right: i16) -> Option<SlipCause> {
+        let stalled =
+            |pwm: i8, ticks: i16| pwm.unsigned_abs() >= self.thresholds.stall_pwm && ticks == 0;
+        let mut sides = Sides::empty();
+        sides.set(Sides::LEFT, stalled(bsd.left_pwm, left));
+        sides.set(Sides::RIGHT, stalled(bsd.right_pwm, right));
+        (!sides.is_empty()).then_some(SlipCause::WheelStalled(sides))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{frame as sensor_frame, inertial, sensors};
+
+    /// A frame after `i` periods of 20 ms, with the wheels turning at the given ticks per
+    /// period.
+    fn frame(i: u16, ticks: (i16, i16), gyro_rate: f32) -> Feedback {
+        let bsd = BasicSensorData {
+            left_pwm: ticks.0.clamp(-100, 100) as i8,
+            right_pwm: ticks.1.clamp(-100, 100) as i8,
+            ..sensors(
+                i * 20,
+                (ticks.0 as u16).wrapping_mul(i),
+                (ticks.1 as u16).wrapping_mul(i),
+            )
+        };
+        sensor_frame(bsd, Some(inertial(0.0, gyro_rate.to_degrees())))
+    }
+
+    #[test]
+    fn test_consistent() {
+        let mut detector = SlipDetector::default();
+        // Spinning on the spot, with the gyro agreeing
+        let geometry = WheelGeometry::default();
+        let rate = 2.0 * 50.0 * geometry.meters_per_tick() * 50.0 / geometry.wheelbase;
+        for i in 0..50 {
+            assert_eq!(detector.update(&frame(i, (-50, 50), rate)), vec![]);
+        }
+    }
+
+    #[test]
+    fn test_yaw_rate_mismatch() {
+        let mut detector = SlipDetector::default();
+        let events: Vec<_> = (0..10)
+            .flat_map(|i| detector.update(&frame(i, (0, 100), 0.0)))
+            .collect();
+        assert!(
+            matches!(
+                events[..],
+                [SlipEvent::SlipDetected(SlipCause::YawRateMismatch {
+                    gyro: 0.0,
+                    ..
+                })]
+            ),
+            "{:?}",
+            events
+        );
+        assert!(detector.is_slipping());
+
+        // Clears once the wheels agree with the gyro again
+        let events: Vec<_> = (10..20)
+            .flat_map(|i| detector.update(&frame(i, (0, 0), 0.0)))
+            .collect();
+        assert_eq!(events, vec![SlipEvent::SlipCleared]);
+    }
+
+    #[test]
+    fn test_transient_mismatch_ignored() {
+        let mut detector = SlipDetector::default();
+        detector.update(&frame(0, (0, 0), 0.0));
+        for i in 1..4 {
+            assert_eq!(detector.update(&frame(i, (0, 0), 1.0)), vec![]);
+        }
+        assert_eq!(detector.update(&frame(4, (0, 0), 0.0)), vec![]);
+        assert!(!detector.is_slipping());
+    }
+
+    #[test]
+    fn test_stall() {
+        let mut detector = SlipDetector::default();
+        let mut stalled = frame(0, (0, 0), 0.0);
+        let events: Vec<_> = (0..10)
+            .flat_map(|i| {
+                let bsd = stalled.basic_sensor_data.as_mut().unwrap();
+                bsd.timestamp = i * 20;
+                bsd.left_pwm = 80;
+                detector.update(&stalled)
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![SlipEvent::SlipDetected(SlipCause::WheelStalled(
+                Sides::LEFT
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_kidnapped() {
+        let mut detector = SlipDetector::default();
+        let mut lifted = frame(1, (0, 0), 0.0);
+        lifted.basic_sensor_data.as_mut().unwrap().wheel_drop = Sides::LEFT | Sides::RIGHT;
+        assert_eq!(detector.update(&lifted), vec![SlipEvent::Kidnapped]);
+        assert!(detector.is_kidnapped());
+        // Spinning wheels are not reported as slip while lifted
+        for i in 2..10 {
+            let mut frame = frame(i, (0, 100), 0.0);
+            frame.basic_sensor_data.as_mut().unwrap().wheel_drop = Sides::all();
+            assert_eq!(detector.update(&frame), vec![]);
+        }
+        assert_eq!(
+            detector.update(&frame(10, (0, 0), 0.0)),
+            vec![SlipEvent::PutDown]
+        );
+    }
+}
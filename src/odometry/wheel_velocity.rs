@@ -0,0 +1,172 @@
+use super::{Unwrapper, WheelGeometry};
+use crate::{control::Twist, rx::BasicSensorData};
+use std::time::Duration;
+
+/// Velocity of each wheel in m/s.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WheelVelocities {
+    pub left: f32,
+    pub right: f32,
+}
+
+impl WheelVelocities {
+    /// The velocity of the base given the distance between the wheels in meters.
+    pub fn twist(&self, wheelbase: f32) -> Twist {
+        Twist::new(
+            (self.left + self.right) / 2.0,
+            (self.right - self.left) / wheelbase,
+        )
+    }
+}
+
+/// Estimates the wheel velocities from the encoders, using the robot timestamps.
+///
+/// The raw velocities are low-pass filtered with the given time constant. Since the interval
+/// is taken from the timestamps, a dropped frame only makes the next interval longer. After
+/// a gap longer than `max_gap`, the filter starts over.
+#[derive(Clone, Debug)]
+pub struct WheelVelocityEstimator {
+    geometry: WheelGeometry,
+    time_constant: Duration,
+    max_gap: Duration,
+    left: Unwrapper,
+    right: Unwrapper,
+    timestamp: Unwrapper,
+    velocities: Option<WheelVelocities>,
+}
+
+impl Default for WheelVelocityEstimator {
+    fn default() -> Self {
+        Self::new(
+            WheelGeometry::default(),
+            Duration::from_millis(50),
+            Duration::from_millis(500),
+        )
+    }
+}
+
+impl WheelVelocityEstimator {
+    pub fn new(geometry: WheelGeometry, time_constant: Duration, max_gap: Duration) -> Self {
+        Self {
+            geometry,
+            time_constant,
+            max_gap,
+            left: Unwrapper::new(),
+            right: Unwrapper::new(),
+            timestamp: Unwrapper::new(),
+            velocities: None,
+        }
+    }
+
+    /// The filtered velocities, or `None` until two frames have been received.
+    pub fn velocities(&self) -> Option<WheelVelocities> {
+        self.velocities
+    }
+
+    /// Updates the estimate from the basic sensor data, and returns the filtered velocities.
+    pub fn update(&mut self, bsd: &BasicSensorData) -> Option<WheelVelocities> {
+        let left = self.left.update(bsd.left_encoder);
+        let right = self.right.update(bsd.right_encoder);
+        let elapsed = self.timestamp.update(bsd.timestamp);
+        if elapsed == 0 {
+            // A repeated frame, or the first one
+            return self.velocities;
+        }
+        let dt = Duration::from_millis(elapsed.unsigned_abs() as u64);
+        if elapsed < 0 || dt > self.max_gap {
+            // The robot rebooted or the link was down, so the interval is not meaningful
+            self.velocities = None;
+            return None;
+        }
+
+        let meters_per_tick = self.geometry.meters_per_tick();
+        let seconds = dt.as_secs_f32();
+        let raw = WheelVelocities {
+            left: left as f32 * meters_per_tick / seconds,
+            right: right as f32 * meters_per_tick / seconds,
+        };
+        let alpha = seconds / (self.time_constant.as_secs_f32() + seconds);
+        let filtered = match self.velocities {
+            Some(previous) => WheelVelocities {
+                left: previous.left + alpha * (raw.left - previous.left),
+                right: previous.right + alpha * (raw.right - previous.right),
+            },
+            None => raw,
+        };
+        self.velocities = Some(filtered);
+        self.velocities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sensors;
+
+    /// Encoder ticks per 20 ms at `speed` m/s.
+    fn ticks(speed: f32) -> u16 {
+        (speed * 0.02 / WheelGeometry::default().meters_per_tick()).round() as u16
+    }
+
+    #[test]
+    fn test_constant_speed_across_wraparound() {
+        let mut estimator = WheelVelocityEstimator::default();
+        assert_eq!(estimator.update(&sensors(65000, 65000, 0)), None);
+        for i in 1..=20u16 {
+            let timestamp = 65000u16.wrapping_add(20 * i);
+            let left = 65000u16.wrapping_add(ticks(0.3) * i);
+            let right = 0u16.wrapping_sub(ticks(0.1) * i);
+            estimator.update(&sensors(timestamp, left, right));
+        }
+        let velocities = estimator.velocities().unwrap();
+        assert!((velocities.left - 0.3).abs() < 0.01, "{:?}", velocities);
+        assert!((velocities.right + 0.1).abs() < 0.01, "{:?}", velocities);
+        let twist = velocities.twist(0.23);
+        assert!((twist.linear - 0.1).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_low_pass() {
+        let mut estimator = WheelVelocityEstimator::default();
+        estimator.update(&sensors(0, 0, 0));
+        estimator.update(&sensors(20, 0, 0));
+        // A step to 0.5 m/s is smoothed over the time constant
+        let step = ticks(0.5);
+        let left = estimator.update(&sensors(40, step, step)).unwrap().left;
+        assert!(left > 0.1 && left < 0.3, "{}", left);
+        for i in 2..20 {
+            estimator.update(&sensors(20 + 20 * i, step * i, step * i));
+        }
+        let left = estimator.velocities().unwrap().left;
+        assert!((left - 0.5).abs() < 0.01, "{}", left);
+    }
+
+    #[test]
+    fn test_dropped_frames() {
+        let mut estimator = WheelVelocityEstimator::default();
+        let step = ticks(0.2);
+        for i in 0..10 {
+            estimator.update(&sensors(20 * i, step * i, step * i));
+        }
+        // Two frames are lost, so the next interval is three times as long
+        let velocities = estimator
+            .update(&sensors(240, step * 12, step * 12))
+            .unwrap();
+        assert!((velocities.left - 0.2).abs() < 0.01, "{:?}", velocities);
+        // Repeated frames do not change the estimate
+        assert_eq!(
+            estimator.update(&sensors(240, step * 12, step * 12)),
+            Some(velocities)
+        );
+    }
+
+    #[test]
+    fn test_long_gap_restarts() {
+        let mut estimator = WheelVelocityEstimator::default();
+        estimator.update(&sensors(0, 0, 0));
+        estimator.update(&sensors(20, 100, 100));
+        assert_eq!(estimator.update(&sensors(1020, 200, 200)), None);
+        let velocities = estimator.update(&sensors(1040, 200, 200)).unwrap();
+        assert_eq!(velocities, WheelVelocities::default());
+    }
+}
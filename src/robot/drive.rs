@@ -0,0 +1,76 @@
+use crate::odometry::Pose;
+
+/// Distance from the goal at which it counts as reached, in meters.
+pub const DISTANCE_TOLERANCE: f32 = 0.005;
+/// Deceleration used when approaching the goal, in m/s².
+const DECELERATION: f32 = 0.5;
+/// Lowest speed used when approaching the goal, so the base does not stall, in m/s.
+const MIN_SPEED: f32 = 0.03;
+
+/// Computes the speed for driving a distance along the initial heading.
+#[derive(Clone, Debug)]
+pub struct DriveDistance {
+    start: Pose,
+    distance: f32,
+    max_speed: f32,
+}
+
+impl DriveDistance {
+    /// Drives `distance` meters from `start`, backwards if negative, at up to `max_speed` m/s.
+    pub fn new(start: Pose, distance: f32, max_speed: f32) -> Self {
+        Self {
+            start,
+            distance,
+            max_speed: max_speed.abs(),
+        }
+    }
+
+    /// Distance travelled along the initial heading, in the direction of the goal.
+    pub fn travelled(&self, pose: &Pose) -> f32 {
+        let (sin, cos) = self.start.theta.sin_cos();
+        let along = (pose.x - self.start.x) * cos + (pose.y - self.start.y) * sin;
+        along * self.distance.signum()
+    }
+
+    /// Returns the linear velocity to drive at, or `None` once the goal is reached.
+    ///
+    /// The speed is reduced near the goal, so the base can stop on it.
+    pub fn step(&self, pose: &Pose) -> Option<f32> {
+        let remaining = self.distance.abs() - self.travelled(pose);
+        if remaining <= DISTANCE_TOLERANCE {
+            return None;
+        }
+        let speed = (2.0 * DECELERATION * remaining)
+            .sqrt()
+            .min(self.max_speed)
+            .max(MIN_SPEED);
+        Some(speed * self.distance.signum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_decelerates_near_goal() {
+        let drive = DriveDistance::new(Pose::default(), 1.5, 0.3);
+        assert_eq!(drive.step(&Pose::default()), Some(0.3));
+        let near = drive.step(&Pose::new(1.45, 0.0, 0.0)).unwrap();
+        assert!((MIN_SPEED..0.3).contains(&near), "{}", near);
+        assert_eq!(drive.step(&Pose::new(1.497, 0.01, 0.0)), None);
+        // Overshooting also counts as reached
+        assert_eq!(drive.step(&Pose::new(1.6, 0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn test_backwards_along_heading() {
+        let start = Pose::new(1.0, 1.0, PI / 2.0);
+        let drive = DriveDistance::new(start, -0.5, 0.2);
+        assert_eq!(drive.step(&start), Some(-0.2));
+        let pose = Pose::new(1.0, 0.7, PI / 2.0);
+        assert!((drive.travelled(&pose) - 0.3).abs() < 1e-6);
+        assert_eq!(drive.step(&Pose::new(1.0, 0.5, PI / 2.0)), None);
+    }
+}
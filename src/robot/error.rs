@@ -0,0 +1,108 @@
+use crate::{
+    control::ProfileError,
+    docking::DockingFailure,
+    rx::{BasicSensorData, Sides, SidesCentral},
+};
+
+/// Why a closed-loop motion did not reach its goal.
+#[derive(Debug)]
+pub enum MotionError {
+    Bumper(SidesCentral),
+    Cliff(SidesCentral),
+    WheelDrop(Sides),
+    /// The emergency stop is engaged.
+    EmergencyStop,
+    /// The goal was not reached in time.
+    Timeout,
+    /// The feedback stopped arriving, or the serial task terminated.
+    Disconnected,
+    /// The motion was cancelled by [`super::Robot::stop`].
+    Stopped,
+    /// The limits or the distance of a profiled motion are invalid.
+    InvalidProfile(ProfileError),
+    /// Docking was given up.
+    Docking(DockingFailure),
+    /// The trajectory to repeat was partly driven backwards.
+    DrivesBackwards,
+    Io(std::io::Error),
+}
+
+impl MotionError {
+    /// Returns the hazard reported by the sensors, if any.
+    ///
+    /// The bumper is only a hazard when driving `forward`, so the base can back away from an
+    /// obstacle it is touching.
+    pub(crate) fn from_sensors(bsd: &BasicSensorData, forward: bool) -> Option<Self> {
+        if !bsd.wheel_drop.is_empty() {
+            Some(Self::WheelDrop(bsd.wheel_drop))
+        } else if !bsd.cliff.is_empty() {
+            Some(Self::Cliff(bsd.cliff))
+        } else if forward && !bsd.bumper.is_empty() {
+            Some(Self::Bumper(bsd.bumper))
+        } else {
+            None
+        }
+    }
+}
+
+impl std::fmt::Display for MotionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bumper(sides) => write!(f, "bumper pressed ({})", sides),
+            Self::Cliff(sides) => write!(f, "cliff detected ({})", sides),
+            Self::WheelDrop(sides) => write!(f, "wheel dropped ({})", sides),
+            Self::EmergencyStop => write!(f, "emergency stop engaged"),
+            Self::Timeout => write!(f, "timed out"),
+            Self::Disconnected => write!(f, "no feedback from the base"),
+            Self::Stopped => write!(f, "stopped"),
+            Self::InvalidProfile(e) => write!(f, "{}", e),
+            Self::Docking(failure) => write!(f, "docking failed: {}", failure),
+            Self::DrivesBackwards => write!(f, "trajectory driven backwards"),
+            Self::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MotionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidProfile(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ProfileError> for MotionError {
+    fn from(e: ProfileError) -> Self {
+        Self::InvalidProfile(e)
+    }
+}
+
+impl From<std::io::Error> for MotionError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sensors;
+
+    #[test]
+    fn test_bumper_only_blocks_forward() {
+        let mut bsd = sensors(0, 0, 0);
+        bsd.bumper = SidesCentral::CENTRAL;
+        assert!(matches!(
+            MotionError::from_sensors(&bsd, true),
+            Some(MotionError::Bumper(SidesCentral::CENTRAL))
+        ));
+        assert!(MotionError::from_sensors(&bsd, false).is_none());
+        bsd.cliff = SidesCentral::LEFT;
+        assert!(matches!(
+            MotionError::from_sensors(&bsd, false),
+            Some(MotionError::Cliff(SidesCentral::LEFT))
+        ));
+    }
+}
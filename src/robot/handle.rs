@@ -0,0 +1,549 @@
+use super::{DriveDistance, MotionError, Rotate, RotationOptions, StopGuard};
+use crate::{
+    control::{HeadingHold, ProfileAxis, ProfileLimits, ProfiledMove, TrapezoidalProfile, Twist},
+    docking::{DockingController, DockingOptions, DockingState},
+    navigation::{PathFollower, PurePursuit, PursuitOptions, Trajectory, TrajectoryRecorder},
+    odometry::{GyroHeading, HeadingSource, Odometry, Pose, Unwrapper, normalize_angle},
+    rx::{DockingIr, Feedback, RobotState},
+    serial_port::{FeedbackReceiver, SerialPortHandler, VelocitySource},
+    tx::{
+        ByteStream,
+        commands::{self, BaseControl},
+    },
+};
+use std::{future::Future, sync::Mutex, time::Duration};
+use tokio::{
+    sync::watch,
+    time::{Instant, timeout, timeout_at},
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Highest linear velocity accepted by the firmware, in m/s.
+pub const MAX_LINEAR_VELOCITY: f32 = 0.7;
+/// Highest angular velocity accepted by the firmware, in rad/s.
+pub const MAX_ANGULAR_VELOCITY: f32 = std::f32::consts::PI;
+
+const BEEP_NOTE: f32 = 880.0;
+const BEEP_DURATION: Duration = Duration::from_millis(100);
+
+/// Time without feedback after which a closed-loop motion is aborted.
+const FEEDBACK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Timeout of the velocity sources of closed-loop motions. They publish on every feedback
+/// frame, and abort once the feedback stops for longer.
+const MOTION_SOURCE_TIMEOUT: Duration = FEEDBACK_TIMEOUT;
+
+/// Priority of the velocity source [`Robot::drive_distance`] drives the base with.
+pub const DRIVE_PRIORITY: u8 = 100;
+const DRIVE_SOURCE: &str = "drive_distance";
+
+/// Priority of the velocity source [`Robot::rotate_by`] and [`Robot::rotate_to`] turn the
+/// base with.
+pub const ROTATE_PRIORITY: u8 = 100;
+const ROTATE_SOURCE: &str = "rotate";
+
+/// Priority of the velocity source [`Robot::drive_profiled`] and [`Robot::rotate_profiled`]
+/// move the base with.
+pub const PROFILED_PRIORITY: u8 = 100;
+const PROFILED_SOURCE: &str = "profiled_move";
+
+/// Priority of the velocity source [`Robot::dock`] drives the base with.
+pub const DOCKING_PRIORITY: u8 = 100;
+const DOCKING_SOURCE: &str = "dock";
+
+/// Priority of the velocity source a path is followed with. Sources registered with a higher
+/// priority, such as a teleop override, take precedence.
+pub const PATH_FOLLOWER_PRIORITY: u8 = 100;
+const PATH_FOLLOWER_SOURCE: &str = "path_follower";
+const PATH_FOLLOWER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A Kobuki base commanded in SI units.
+///
+/// Wraps a [`SerialPortHandler`], and takes care of converting velocities into the
+/// speed/radius pairs understood by the firmware. The pose of the base is tracked in the
+/// background from the encoders and the gyro, starting at the origin.
+pub struct Robot {
+    handler: SerialPortHandler,
+    rotation: RotationOptions,
+    odometry_tx: watch::Sender<Odometry>,
+    _odometry_task: DropGuard,
+    /// Cancelled by [`Self::stop`], to end the motions in progress.
+    motions: Mutex<CancellationToken>,
+}
+
+impl Robot {
+    /// Wraps `handler`, and starts tracking the odometry in the background.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime, since the odometry is tracked by a task.
+    pub fn new(handler: SerialPortHandler) -> Self {
+        let odometry = Odometry::default().heading_source(HeadingSource::Gyro { blend: 0.95 });
+        let (odometry_tx, _) = watch::channel(odometry);
+        let cancel = CancellationToken::new();
+        let task = track_odometry(handler.feedback_receiver(), odometry_tx.clone());
+        let token = cancel.clone();
+        tokio::spawn(async move { token.run_until_cancelled(task).await });
+        Self {
+            handler,
+            rotation: RotationOptions::default(),
+            odometry_tx,
+            _odometry_task: cancel.drop_guard(),
+            motions: Mutex::new(CancellationToken::new()),
+        }
+    }
+
+    /// Sets how [`Self::rotate_by`] and [`Self::rotate_to`] approach the goal.
+    pub fn rotation_options(mut self, options: RotationOptions) -> Self {
+        self.rotation = options;
+        self
+    }
+
+    /// Opens the serial port at `path` using the default configuration, with the heading
+    /// hold enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio runtime, like [`Self::new`].
+    pub fn open(path: &str) -> tokio_serial::Result<Self> {
+        let handler = SerialPortHandler::builder()
+            .heading_hold(HeadingHold::DEFAULT_GAINS)
+            .open(path)?;
+        Ok(Self::new(handler))
+    }
+
+    /// Returns the underlying handler, for access to the raw commands and feedback.
+    pub fn handler(&self) -> &SerialPortHandler {
+        &self.handler
+    }
+
+    /// Sets the velocity of the base, in m/s forward and rad/s counter-clockwise.
+    ///
+    /// The velocities are scaled down to [`MAX_LINEAR_VELOCITY`] and [`MAX_ANGULAR_VELOCITY`],
+    /// and so that the outer wheel does not exceed [`MAX_LINEAR_VELOCITY`] on an arc. Like
+    /// [`SerialPortHandler::set_velocity`], the base is stopped if the velocity is not
+    /// refreshed within the deadman timeout.
+    pub fn set_twist(&self, linear: f32, angular: f32) -> std::io::Result<()> {
+        let cmd = twist_command(linear, angular);
+        self.handler.set_velocity(cmd.speed(), cmd.radius())
+    }
+
+    /// Stops the base.
+    ///
+    /// The closed-loop motions in progress end with [`MotionError::Stopped`], and every
+    /// velocity source of the mux is released. A source registered through the
+    /// [`Self::handler`] takes control again when it next publishes.
+    pub fn stop(&self) -> std::io::Result<()> {
+        let motions = std::mem::take(&mut *self.motions.lock().unwrap());
+        motions.cancel();
+        self.handler.release_velocity_sources();
+        self.handler.set_velocity(0, 0)
+    }
+
+    /// Plays a short beep.
+    pub async fn beep(&self) -> std::io::Result<()> {
+        let cmd = ByteStream::builder().subpayload(commands::Sound::new(BEEP_NOTE, BEEP_DURATION));
+        self.handler.send_command(cmd).await
+    }
+
+    /// Returns the latest value of every feedback sub-payload.
+    pub fn state(&self) -> RobotState {
+        self.handler.robot_state().borrow().clone()
+    }
+
+    /// The pose of the base in the odometry frame.
+    pub fn pose(&self) -> Pose {
+        self.odometry_tx.borrow().pose()
+    }
+
+    /// Moves the odometry frame, so the base is at `pose`.
+    pub fn reset_pose(&self, pose: Pose) {
+        self.odometry_tx
+            .send_modify(|odometry| odometry.reset(pose));
+    }
+
+    /// Returns a receiver notified on every odometry update.
+    pub fn odometry(&self) -> watch::Receiver<Odometry> {
+        self.odometry_tx.subscribe()
+    }
+
+    /// Follows the waypoints `(x, y)` in the odometry frame, with pure pursuit.
+    ///
+    /// The path starts at the current position of the base. The returned stream reports the
+    /// progress, and ends when the last waypoint is reached or the motion is aborted, for the
+    /// same reasons as [`Self::drive_distance`]. Dropping the stream stops the base.
+    ///
+    /// The base is driven through a velocity source of its own, at
+    /// [`PATH_FOLLOWER_PRIORITY`].
+    pub fn follow_path(
+        &self,
+        waypoints: impl IntoIterator<Item = (f32, f32)>,
+        options: PursuitOptions,
+    ) -> PathFollower {
+        PathFollower::spawn(
+            PurePursuit::new(waypoints, options),
+            self.handler.velocity_source(
+                PATH_FOLLOWER_SOURCE,
+                PATH_FOLLOWER_PRIORITY,
+                PATH_FOLLOWER_TIMEOUT,
+            ),
+            self.handler.emergency_stop_state(),
+            self.odometry(),
+            self.handler.robot_state(),
+            self.stopped(),
+        )
+    }
+
+    /// Starts recording the route driven, for example by teleop, with a sample every
+    /// `min_distance` meters or `min_rotation` radians.
+    ///
+    /// Each sample holds the pose in the odometry frame, and the command of the velocity
+    /// source controlling the base at the time.
+    pub fn record_trajectory(&self, min_distance: f32, min_rotation: f32) -> TrajectoryRecorder {
+        TrajectoryRecorder::spawn(
+            min_distance,
+            min_rotation,
+            self.handler.command_sender(),
+            self.odometry(),
+        )
+    }
+
+    /// Follows the positions of a recorded route again, see [`Self::follow_path`].
+    ///
+    /// The base first drives from where it is to the start of the route, so the route is
+    /// followed where it was recorded in the odometry frame. To follow it relative to the
+    /// base instead, after placing the base where and as the recording started, align the
+    /// route first with [`Trajectory::aligned_to`] and the current [`Self::pose`].
+    ///
+    /// The route is always followed forward, at the velocities of `options`. A route with
+    /// parts driven backwards is rejected, and the follower aborts right away with
+    /// [`MotionError::DrivesBackwards`].
+    pub fn repeat_trajectory(
+        &self,
+        trajectory: &Trajectory,
+        options: PursuitOptions,
+    ) -> PathFollower {
+        if trajectory.drives_backwards() {
+            return PathFollower::aborted(MotionError::DrivesBackwards);
+        }
+        self.follow_path(trajectory.waypoints(), options)
+    }
+
+    /// Drives `meters` straight ahead, or backwards if negative, at up to `speed` m/s.
+    ///
+    /// The distance is measured by the encoders, and the base slows down when approaching
+    /// the goal. The base is commanded straight ahead, so with the heading hold of
+    /// [`crate::serial_port::SerialPortHandlerBuilder::heading_hold`], the gyro heading at
+    /// the start is held and the base does not drift sideways. Fails if a cliff or wheel drop
+    /// sensor triggers, if a bumper triggers while driving forward, or if the emergency stop
+    /// is engaged. Dropping the future cancels the motion and stops the base.
+    ///
+    /// The base is driven through a velocity source of its own, at [`DRIVE_PRIORITY`].
+    pub async fn drive_distance(&self, meters: f32, speed: f32) -> Result<(), MotionError> {
+        let forward = meters >= 0.0;
+        let mut feedback = self.handler.feedback_receiver();
+        let source = self.motion_source(DRIVE_SOURCE, DRIVE_PRIORITY);
+        let _guard = StopGuard(&source);
+        self.stoppable(async {
+            let mut odometry = Odometry::default();
+            let mut drive = None;
+            loop {
+                let frame = self.next_feedback(&mut feedback, forward).await?;
+                if !odometry.update(&frame) {
+                    continue;
+                }
+                let pose = odometry.pose();
+                let drive = drive.get_or_insert_with(|| DriveDistance::new(pose, meters, speed));
+                match drive.step(&pose) {
+                    Some(linear) => set_twist(&source, linear, 0.0)?,
+                    None => return Ok(()),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Drives `meters` straight ahead, or backwards if negative, along a trapezoidal velocity
+    /// profile in m/s and m/s².
+    ///
+    /// Unlike [`Self::drive_distance`], the velocity only depends on the time elapsed on the
+    /// robot clock, so the move is repeatable but not corrected by the encoders.
+    /// Fails for the same reasons as [`Self::drive_distance`], and with
+    /// [`MotionError::InvalidProfile`] if the limits are invalid, see
+    /// [`TrapezoidalProfile::try_new`]. The maximum velocity is capped at
+    /// [`MAX_LINEAR_VELOCITY`].
+    ///
+    /// The base is driven through a velocity source of its own, at [`PROFILED_PRIORITY`].
+    pub async fn drive_profiled(
+        &self,
+        meters: f32,
+        limits: ProfileLimits,
+    ) -> Result<(), MotionError> {
+        let profile = TrapezoidalProfile::try_new(meters, capped(limits, MAX_LINEAR_VELOCITY))?;
+        self.play(
+            ProfiledMove::new(profile, ProfileAxis::Linear),
+            meters >= 0.0,
+        )
+        .await
+    }
+
+    /// Rotates on the spot by `radians`, counter-clockwise if positive, along a trapezoidal
+    /// velocity profile in rad/s and rad/s².
+    ///
+    /// Fails like [`Self::drive_profiled`]. The maximum velocity is capped at
+    /// [`MAX_ANGULAR_VELOCITY`].
+    pub async fn rotate_profiled(
+        &self,
+        radians: f32,
+        limits: ProfileLimits,
+    ) -> Result<(), MotionError> {
+        let profile = TrapezoidalProfile::try_new(radians, capped(limits, MAX_ANGULAR_VELOCITY))?;
+        self.play(ProfiledMove::new(profile, ProfileAxis::Angular), true)
+            .await
+    }
+
+    /// Sends the command of the profile for every feedback frame, until it is over.
+    async fn play(&self, mut profiled: ProfiledMove, forward: bool) -> Result<(), MotionError> {
+        let mut feedback = self.handler.feedback_receiver();
+        let source = self.motion_source(PROFILED_SOURCE, PROFILED_PRIORITY);
+        let _guard = StopGuard(&source);
+        self.stoppable(async {
+            loop {
+                let frame = self.next_feedback(&mut feedback, forward).await?;
+                let Some(bsd) = &frame.basic_sensor_data else {
+                    continue;
+                };
+                match profiled.update(bsd) {
+                    Some(cmd) => source.set_velocity(cmd.speed(), cmd.radius())?,
+                    None => return Ok(()),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Rotates on the spot by `radians`, counter-clockwise if positive.
+    ///
+    /// The rotation is controlled by the gyro angle, and may be more than a full turn. Fails
+    /// with [`MotionError::Timeout`] if the base has not settled on the goal within the
+    /// timeout of the [`RotationOptions`].
+    ///
+    /// The base is turned through a velocity source of its own, at [`ROTATE_PRIORITY`].
+    pub async fn rotate_by(&self, radians: f32) -> Result<(), MotionError> {
+        self.rotate(|start| start + radians).await
+    }
+
+    /// Rotates on the spot to `heading` in radians, taking the shortest direction.
+    ///
+    /// The heading is in the frame of the gyro, which is zeroed when the robot boots.
+    pub async fn rotate_to(&self, heading: f32) -> Result<(), MotionError> {
+        self.rotate(|start| start + normalize_angle(heading - start))
+            .await
+    }
+
+    /// Rotates to the goal computed from the heading at the start.
+    async fn rotate(&self, goal: impl Fn(f32) -> f32) -> Result<(), MotionError> {
+        let mut feedback = self.handler.feedback_receiver();
+        let source = self.motion_source(ROTATE_SOURCE, ROTATE_PRIORITY);
+        let _guard = StopGuard(&source);
+        self.stoppable(async {
+            let deadline = Instant::now() + self.rotation.timeout;
+            let mut gyro = GyroHeading::default();
+            let mut timestamp = Unwrapper::new();
+            let mut rotate = None;
+            let mut start = 0.0;
+            loop {
+                let frame = timeout_at(deadline, self.next_feedback(&mut feedback, true))
+                    .await
+                    .map_err(|_| MotionError::Timeout)??;
+                let (Some(bsd), Some(inertial)) =
+                    (&frame.basic_sensor_data, &frame.inertial_sensor)
+                else {
+                    continue;
+                };
+                // Timed by the robot clock, since frames decoded together arrive in a burst
+                let dt = Duration::from_millis(timestamp.update(bsd.timestamp).max(0) as u64);
+                if !gyro.has_reading() {
+                    start = inertial.angle.to_radians();
+                }
+                gyro.update(inertial.angle);
+                let heading = start + gyro.heading();
+                let rotate = rotate.get_or_insert_with(|| Rotate::new(goal(start), self.rotation));
+                match rotate.step(heading, inertial.angle_rate.to_radians(), dt) {
+                    Some(angular) => set_twist(&source, 0.0, angular)?,
+                    None => return Ok(()),
+                }
+            }
+        })
+        .await
+    }
+
+    /// Drives onto the docking station, using the docking IR sensors.
+    ///
+    /// Succeeds once the charger reports the base as docked. Bumping into the dock is part
+    /// of docking, but a cliff or wheel drop aborts it. Dropping the future stops the base.
+    ///
+    /// The base is driven through a velocity source of its own, at [`DOCKING_PRIORITY`].
+    pub async fn dock(&self, options: DockingOptions) -> Result<(), MotionError> {
+        let mut feedback = self.handler.feedback_receiver();
+        let source = self.motion_source(DOCKING_SOURCE, DOCKING_PRIORITY);
+        let _guard = StopGuard(&source);
+        self.stoppable(async {
+            let mut odometry = Odometry::default();
+            let mut controller = DockingController::new(options);
+            let mut ir = DockingIr::default();
+            loop {
+                let frame = self.receive_feedback(&mut feedback).await?;
+                if let Some(docking_ir) = &frame.docking_ir {
+                    ir = docking_ir.clone();
+                }
+                odometry.update(&frame);
+                let Some(bsd) = &frame.basic_sensor_data else {
+                    continue;
+                };
+                if let Some(hazard) = MotionError::from_sensors(bsd, false) {
+                    return Err(hazard);
+                }
+                match controller.update(&ir, bsd, &odometry.pose()) {
+                    Some(cmd) => source.set_velocity(cmd.speed(), cmd.radius())?,
+                    None => match controller.state() {
+                        DockingState::Failed(failure) => {
+                            return Err(MotionError::Docking(failure));
+                        }
+                        _ => return Ok(()),
+                    },
+                }
+            }
+        })
+        .await
+    }
+
+    /// Returns a token cancelled by the next call to [`Self::stop`].
+    fn stopped(&self) -> CancellationToken {
+        self.motions.lock().unwrap().clone()
+    }
+
+    /// Registers the velocity source a closed-loop motion drives the base with.
+    fn motion_source(&self, name: &str, priority: u8) -> VelocitySource {
+        self.handler
+            .velocity_source(name, priority, MOTION_SOURCE_TIMEOUT)
+    }
+
+    /// Runs a closed-loop motion, until it ends or [`Self::stop`] is called.
+    async fn stoppable(
+        &self,
+        motion: impl Future<Output = Result<(), MotionError>>,
+    ) -> Result<(), MotionError> {
+        self.stopped()
+            .run_until_cancelled(motion)
+            .await
+            .unwrap_or(Err(MotionError::Stopped))
+    }
+
+    /// Waits for the next feedback frame, failing if the motion has to be aborted.
+    async fn next_feedback(
+        &self,
+        rx: &mut FeedbackReceiver,
+        forward: bool,
+    ) -> Result<Feedback, MotionError> {
+        let feedback = self.receive_feedback(rx).await?;
+        if let Some(hazard) = feedback
+            .basic_sensor_data
+            .as_ref()
+            .and_then(|bsd| MotionError::from_sensors(bsd, forward))
+        {
+            return Err(hazard);
+        }
+        Ok(feedback)
+    }
+
+    /// Waits for the next feedback frame, failing if it does not arrive in time or the
+    /// emergency stop is engaged.
+    async fn receive_feedback(&self, rx: &mut FeedbackReceiver) -> Result<Feedback, MotionError> {
+        let feedback = timeout(FEEDBACK_TIMEOUT, rx.recv())
+            .await
+            .map_err(|_| MotionError::Disconnected)?
+            .map_err(|_| MotionError::Disconnected)?;
+        if self.handler.emergency_stop_state().borrow().is_engaged() {
+            return Err(MotionError::EmergencyStop);
+        }
+        Ok(feedback)
+    }
+}
+
+/// Converts a twist into the command for the firmware, within its limits.
+fn twist_command(linear: f32, angular: f32) -> BaseControl {
+    Twist::new(linear, angular)
+        .clamp(MAX_LINEAR_VELOCITY, MAX_ANGULAR_VELOCITY)
+        .to_base_control()
+}
+
+/// Sets the velocity of a motion source, within the limits of the firmware.
+fn set_twist(source: &VelocitySource, linear: f32, angular: f32) -> std::io::Result<()> {
+    let cmd = twist_command(linear, angular);
+    source.set_velocity(cmd.speed(), cmd.radius())
+}
+
+/// Caps the maximum velocity of a profile at the firmware limit.
+fn capped(limits: ProfileLimits, max_velocity: f32) -> ProfileLimits {
+    ProfileLimits {
+        max_velocity: limits.max_velocity.min(max_velocity),
+        ..limits
+    }
+}
+
+/// Updates the odometry from every feedback frame, until the serial task stops.
+async fn track_odometry(mut feedback: FeedbackReceiver, odometry_tx: watch::Sender<Odometry>) {
+    while let Ok(frame) = feedback.recv().await {
+        odometry_tx.send_if_modified(|odometry| odometry.update(&frame));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sensors;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_twist_command() {
+        assert_eq!(twist_command(0.2, 0.0), *BaseControl::new(200, 0));
+        assert_eq!(twist_command(-1.0, 0.0), *BaseControl::new(-700, 0));
+        // Spinning on the spot at the highest angular velocity
+        assert_eq!(twist_command(0.0, 4.0), *BaseControl::new(361, 1));
+    }
+
+    #[test]
+    fn test_capped_profile() {
+        let limits = ProfileLimits::new(2.0, 1.0, 1.0);
+        let profile = TrapezoidalProfile::new(10.0, capped(limits, MAX_LINEAR_VELOCITY));
+        assert_eq!(profile.peak_velocity(), MAX_LINEAR_VELOCITY);
+        let mut profiled = ProfiledMove::new(profile, ProfileAxis::Linear);
+        let mut fastest = 0;
+        let mut timestamp = 0u16;
+        while let Some(cmd) = profiled.update(&sensors(timestamp, 0, 0)) {
+            fastest = fastest.max(cmd.speed());
+            timestamp = timestamp.wrapping_add(20);
+        }
+        assert_eq!(fastest, 700);
+        assert_eq!(
+            capped(ProfileLimits::new(0.2, 1.0, 1.0), 0.7).max_velocity,
+            0.2
+        );
+    }
+
+    #[test]
+    fn test_twist_command_arc() {
+        // The outer wheel would drive at 1.06 m/s, so both velocities are scaled down
+        for (linear, angular) in [(0.7, PI), (0.7, -PI), (-0.7, PI), (2.0, 1.0)] {
+            let cmd = twist_command(linear, angular);
+            assert!(cmd.speed().abs() <= 700, "{:?}", cmd);
+            let radius = linear / angular * 1000.0;
+            assert!((cmd.radius() as f32 - radius).abs() <= 1.0, "{:?}", cmd);
+        }
+        let cmd = twist_command(0.7, PI);
+        assert_eq!(cmd, *BaseControl::new(700, 223));
+        let twist = Twist::from(cmd);
+        assert!(twist.linear < 0.7 && twist.angular < PI);
+    }
+}
@@ -0,0 +1,14 @@
+mod drive;
+mod error;
+mod handle;
+mod rotate;
+mod stop_guard;
+
+pub use drive::{DISTANCE_TOLERANCE, DriveDistance};
+pub use error::MotionError;
+pub use handle::{
+    DOCKING_PRIORITY, DRIVE_PRIORITY, MAX_ANGULAR_VELOCITY, MAX_LINEAR_VELOCITY,
+    PATH_FOLLOWER_PRIORITY, PROFILED_PRIORITY, ROTATE_PRIORITY, Robot,
+};
+pub use rotate::{Rotate, RotationOptions};
+pub(crate) use stop_guard::StopGuard;
@@ -0,0 +1,107 @@
+use super::MAX_ANGULAR_VELOCITY;
+use crate::control::{Pid, PidGains};
+use std::time::Duration;
+
+/// How [`super::Robot::rotate_by`] and [`super::Robot::rotate_to`] approach the goal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RotationOptions {
+    pub gains: PidGains,
+    /// Highest angular velocity used, in rad/s.
+    pub max_angular: f32,
+    /// Heading error at which the goal counts as reached, in radians.
+    pub tolerance: f32,
+    /// Angular rate below which the base counts as settled, in rad/s.
+    pub settle_rate: f32,
+    /// Time after which the rotation is aborted.
+    pub timeout: Duration,
+}
+
+impl Default for RotationOptions {
+    fn default() -> Self {
+        Self {
+            gains: PidGains::new(2.5, 0.5, 0.05),
+            max_angular: 1.5,
+            tolerance: 1.0f32.to_radians(),
+            settle_rate: 0.05,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Computes the angular velocity for rotating to a heading.
+#[derive(Clone, Debug)]
+pub struct Rotate {
+    goal: f32,
+    pid: Pid,
+    options: RotationOptions,
+}
+
+impl Rotate {
+    /// Rotates to `goal`, an unwrapped heading in radians, so turns of more than π are kept.
+    pub fn new(goal: f32, options: RotationOptions) -> Self {
+        let max_angular = options.max_angular.min(MAX_ANGULAR_VELOCITY);
+        Self {
+            goal,
+            pid: Pid::new(options.gains).output_limit(max_angular),
+            options,
+        }
+    }
+
+    /// Returns the angular velocity to rotate at, or `None` once the base has settled on the
+    /// goal.
+    ///
+    /// Takes the unwrapped heading in radians and the angular rate in rad/s, measured `dt`
+    /// after the previous step.
+    pub fn step(&mut self, heading: f32, rate: f32, dt: Duration) -> Option<f32> {
+        let error = self.goal - heading;
+        if error.abs() <= self.options.tolerance && rate.abs() <= self.options.settle_rate {
+            return None;
+        }
+        Some(self.pid.update(error, dt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    const DT: Duration = Duration::from_millis(20);
+
+    /// Simulates a base following the commanded angular velocity with some lag.
+    fn simulate(goal: f32, start: f32) -> (usize, f32) {
+        let mut rotate = Rotate::new(goal, RotationOptions::default());
+        let (mut heading, mut rate) = (start, 0.0);
+        for i in 0..1000 {
+            let Some(angular) = rotate.step(heading, rate, DT) else {
+                return (i, heading);
+            };
+            rate += (angular - rate) * 0.3;
+            heading += rate * DT.as_secs_f32();
+        }
+        panic!("did not settle at {}, heading {}", goal, heading);
+    }
+
+    #[test]
+    fn test_settles_on_goal() {
+        let (_, heading) = simulate(PI / 2.0, 0.0);
+        assert!((heading - PI / 2.0).abs() <= 1.0f32.to_radians());
+        // More than half a turn is not taken the short way
+        let (_, heading) = simulate(-1.5 * PI, 0.0);
+        assert!((heading + 1.5 * PI).abs() <= 1.0f32.to_radians());
+    }
+
+    #[test]
+    fn test_waits_for_settling() {
+        let mut rotate = Rotate::new(1.0, RotationOptions::default());
+        // On the goal, but still turning
+        assert!(rotate.step(1.0, 0.5, DT).is_some());
+        assert_eq!(rotate.step(1.0, 0.01, DT), None);
+    }
+
+    #[test]
+    fn test_limits_angular_velocity() {
+        let mut rotate = Rotate::new(10.0, RotationOptions::default());
+        assert_eq!(rotate.step(0.0, 0.0, DT), Some(1.5));
+    }
+}
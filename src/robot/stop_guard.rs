@@ -0,0 +1,28 @@
+use crate::serial_port::{SerialPortHandler, VelocitySource};
+
+/// Something the velocity of the base is commanded through.
+pub(crate) trait SetVelocity {
+    fn set_velocity(&self, speed: i16, radius: i16) -> std::io::Result<()>;
+}
+
+impl SetVelocity for SerialPortHandler {
+    fn set_velocity(&self, speed: i16, radius: i16) -> std::io::Result<()> {
+        SerialPortHandler::set_velocity(self, speed, radius)
+    }
+}
+
+impl SetVelocity for VelocitySource {
+    fn set_velocity(&self, speed: i16, radius: i16) -> std::io::Result<()> {
+        VelocitySource::set_velocity(self, speed, radius)
+    }
+}
+
+/// Stops the base when a motion ends, including when its future is dropped or its task is
+/// cancelled.
+pub(crate) struct StopGuard<'a, T: SetVelocity>(pub(crate) &'a T);
+
+impl<T: SetVelocity> Drop for StopGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.set_velocity(0, 0).ok(); // stopping is always allowed - ignore errors
+    }
+}
@@ -1,13 +1,19 @@
 use super::basic_sensor_data::BasicSensorData;
 use super::docking_ir::DockingIr;
 use super::feedback_decoder::FeedbackId;
+use super::gyro::Gyro;
 use super::inertial_sensor::InertialSensor;
+use std::time::Duration;
+
+/// Interval between two feedback frames, since the base sends feedback at 50 Hz.
+pub const FEEDBACK_PERIOD: Duration = Duration::from_millis(20);
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct Feedback {
     pub basic_sensor_data: Option<BasicSensorData>,
     pub docking_ir: Option<DockingIr>,
     pub inertial_sensor: Option<InertialSensor>,
+    pub gyro: Option<Gyro>,
 }
 
 impl Feedback {
@@ -24,6 +30,7 @@ impl Feedback {
             FeedbackId::InertialSensor => {
                 self.inertial_sensor = Some(InertialSensor::try_from(data)?)
             }
+            FeedbackId::Gyro => self.gyro = Some(Gyro::try_from(data)?),
             _ => {}
         }
         Ok(())
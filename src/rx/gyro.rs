@@ -0,0 +1,71 @@
+/// Angular velocity per digit of the raw gyro samples, in degrees per second.
+pub const GYRO_DEGREES_PER_DIGIT: f32 = 0.00875;
+
+/// Raw samples of the 3-axis gyro, taken since the previous frame.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Gyro {
+    pub frame_id: u8,
+    /// Samples of the x, y and z axes in digits.
+    pub samples: Vec<[i16; 3]>,
+}
+
+impl Gyro {
+    /// Average angular velocity around the z axis, in radians per second.
+    pub fn yaw_rate(&self) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let sum: f32 = self.samples.iter().map(|sample| sample[2] as f32).sum();
+        Some((sum / self.samples.len() as f32 * GYRO_DEGREES_PER_DIGIT).to_radians())
+    }
+}
+
+impl TryFrom<&[u8]> for Gyro {
+    type Error = std::io::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let invalid_length = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid gyro data length: {}", data.len()),
+            )
+        };
+        let [frame_id, length, samples @ ..] = data else {
+            return Err(invalid_length());
+        };
+        if samples.len() != *length as usize * 2 || length % 3 != 0 {
+            return Err(invalid_length());
+        }
+
+        Ok(Self {
+            frame_id: *frame_id,
+            samples: samples
+                .chunks_exact(6)
+                .map(|sample| [0, 2, 4].map(|i| i16::from_le_bytes([sample[i], sample[i + 1]])))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let data = [
+            0xee, 0x06, 0x2a, 0x00, 0x92, 0xff, 0xbc, 0xff, 0x23, 0x00, 0x98, 0xff, 0xb9, 0xff,
+        ];
+        let gyro = Gyro::try_from(data.as_ref()).unwrap();
+        assert_eq!(gyro.frame_id, 0xee);
+        assert_eq!(gyro.samples, vec![[42, -110, -68], [35, -104, -71]]);
+        let expected = (-69.5 * GYRO_DEGREES_PER_DIGIT).to_radians();
+        assert!((gyro.yaw_rate().unwrap() - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        assert!(Gyro::try_from([0xee, 0x03, 0x00, 0x00].as_ref()).is_err());
+        assert!(Gyro::try_from([0xee].as_ref()).is_err());
+    }
+}
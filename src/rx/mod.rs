@@ -2,9 +2,14 @@ mod basic_sensor_data;
 mod docking_ir;
 mod feedback;
 mod feedback_decoder;
+mod gyro;
 mod inertial_sensor;
+mod robot_state;
 
+pub use basic_sensor_data::{BasicSensorData, Button, Charger, Sides, SidesCentral};
 pub use docking_ir::{DockingIr, IrSignal};
-pub use feedback::Feedback;
+pub use feedback::{FEEDBACK_PERIOD, Feedback};
 pub use feedback_decoder::FeedbackDecoder;
+pub use gyro::{GYRO_DEGREES_PER_DIGIT, Gyro};
 pub use inertial_sensor::InertialSensor;
+pub use robot_state::{RobotState, Timestamped};
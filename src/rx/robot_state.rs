@@ -0,0 +1,119 @@
+use super::basic_sensor_data::BasicSensorData;
+use super::docking_ir::DockingIr;
+use super::feedback::Feedback;
+use super::gyro::Gyro;
+use super::inertial_sensor::InertialSensor;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A sub-payload value together with the time it was received.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timestamped<T> {
+    pub value: T,
+    pub received: Instant,
+}
+
+impl<T> Timestamped<T> {
+    pub fn new(value: T, received: Instant) -> Self {
+        Self { value, received }
+    }
+
+    /// Time elapsed since the value was received.
+    pub fn age(&self) -> Duration {
+        self.received.elapsed()
+    }
+}
+
+/// The latest known value of every feedback sub-payload.
+///
+/// Each [`Feedback`] frame only contains some of the sub-payloads. The robot state merges the
+/// frames, so every field holds the most recent value received, or `None` if it has never been
+/// received.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RobotState {
+    pub basic_sensor_data: Option<Timestamped<BasicSensorData>>,
+    pub docking_ir: Option<Timestamped<DockingIr>>,
+    pub inertial_sensor: Option<Timestamped<InertialSensor>>,
+    pub gyro: Option<Timestamped<Gyro>>,
+}
+
+impl RobotState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges the sub-payloads present in `feedback` into the state.
+    pub fn update(&mut self, feedback: &Feedback, received: Instant) {
+        if let Some(bsd) = &feedback.basic_sensor_data {
+            self.basic_sensor_data = Some(Timestamped::new(bsd.clone(), received));
+        }
+        if let Some(docking_ir) = &feedback.docking_ir {
+            self.docking_ir = Some(Timestamped::new(docking_ir.clone(), received));
+        }
+        if let Some(inertial_sensor) = &feedback.inertial_sensor {
+            self.inertial_sensor = Some(Timestamped::new(inertial_sensor.clone(), received));
+        }
+        if let Some(gyro) = &feedback.gyro {
+            self.gyro = Some(Timestamped::new(gyro.clone(), received));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rx::IrSignal;
+
+    #[test]
+    fn test_update_keeps_missing_subpayloads() {
+        let first = Instant::now();
+        let second = first + Duration::from_millis(20);
+        let mut state = RobotState::new();
+
+        let mut feedback = Feedback::new();
+        feedback.inertial_sensor = Some(InertialSensor {
+            angle: 10.0,
+            angle_rate: 1.0,
+        });
+        state.update(&feedback, first);
+
+        let mut feedback = Feedback::new();
+        feedback.docking_ir = Some(DockingIr {
+            right: IrSignal::FAR_LEFT,
+            center: IrSignal::empty(),
+            left: IrSignal::empty(),
+        });
+        state.update(&feedback, second);
+
+        let inertial_sensor = state.inertial_sensor.unwrap();
+        assert_eq!(inertial_sensor.value.angle, 10.0);
+        assert_eq!(inertial_sensor.received, first);
+        let docking_ir = state.docking_ir.unwrap();
+        assert_eq!(docking_ir.value.right, IrSignal::FAR_LEFT);
+        assert_eq!(docking_ir.received, second);
+        assert_eq!(state.basic_sensor_data, None);
+    }
+
+    #[test]
+    fn test_update_replaces_older_values() {
+        let first = Instant::now();
+        let second = first + Duration::from_millis(20);
+        let mut state = RobotState::new();
+
+        let mut feedback = Feedback::new();
+        feedback.basic_sensor_data = Some(BasicSensorData {
+            timestamp: 1,
+            ..Default::default()
+        });
+        state.update(&feedback, first);
+        feedback.basic_sensor_data = Some(BasicSensorData {
+            timestamp: 2,
+            ..Default::default()
+        });
+        state.update(&feedback, second);
+
+        let bsd = state.basic_sensor_data.unwrap();
+        assert_eq!(bsd.value.timestamp, 2);
+        assert_eq!(bsd.received, second);
+    }
+}
@@ -0,0 +1,197 @@
+use super::{LagPolicy, SerialPortHandler};
+use crate::{
+    control::{PidGains, SafetyPolicy, SmootherLimits},
+    rx::Button,
+};
+use std::time::Duration;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// Configures and starts a [`SerialPortHandler`].
+///
+/// The safety supervisor is opt-in: unless a [`Self::safety_policy`] is set, velocity
+/// commands are sent even while a bumper, cliff or wheel drop sensor is triggered.
+///
+/// ```no_run
+/// # use kobuki_interface::serial_port::{LagPolicy, SerialPortHandler};
+/// # async fn example() -> tokio_serial::Result<()> {
+/// let handler = SerialPortHandler::builder()
+///     .feedback_capacity(50)
+///     .lag_policy(LagPolicy::SkipToLatest)
+///     .open("/dev/kobuki")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SerialPortHandlerBuilder {
+    pub(super) feedback_capacity: usize,
+    pub(super) command_capacity: usize,
+    pub(super) lag_policy: LagPolicy,
+    pub(super) baud_rate: u32,
+    pub(super) timeout: Duration,
+    pub(super) stale_timeout: Duration,
+    pub(super) reconnect_interval: Duration,
+    pub(super) keep_alive_period: Duration,
+    pub(super) deadman_timeout: Duration,
+    pub(super) velocity_smoother: Option<SmootherLimits>,
+    pub(super) heading_hold: Option<PidGains>,
+    pub(super) safety_policy: Option<SafetyPolicy>,
+    pub(super) emergency_stop_button: Button,
+    pub(super) heartbeat_timeout: Option<Duration>,
+    pub(super) task_name: String,
+}
+
+impl Default for SerialPortHandlerBuilder {
+    fn default() -> Self {
+        Self {
+            feedback_capacity: 10,
+            command_capacity: 10,
+            lag_policy: LagPolicy::default(),
+            baud_rate: 115200,
+            timeout: Duration::from_millis(1024),
+            // The base sends feedback at 50 Hz, so this corresponds to ten missed frames.
+            stale_timeout: Duration::from_millis(200),
+            reconnect_interval: Duration::from_secs(1),
+            keep_alive_period: Duration::from_millis(100),
+            deadman_timeout: Duration::from_secs(2),
+            velocity_smoother: None,
+            heading_hold: None,
+            safety_policy: None,
+            emergency_stop_button: Button::empty(),
+            heartbeat_timeout: None,
+            task_name: "kobuki".to_string(),
+        }
+    }
+}
+
+impl SerialPortHandlerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of feedback frames buffered for each subscriber before it starts lagging.
+    pub fn feedback_capacity(mut self, capacity: usize) -> Self {
+        self.feedback_capacity = capacity;
+        self
+    }
+
+    /// Number of commands queued for the serial port before senders have to wait.
+    pub fn command_capacity(mut self, capacity: usize) -> Self {
+        self.command_capacity = capacity;
+        self
+    }
+
+    /// How receivers from [`SerialPortHandler::feedback_receiver`] catch up after falling
+    /// behind.
+    pub fn lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// Baud rate used when opening the port with [`Self::open`].
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Serial port timeout used when opening the port with [`Self::open`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time without a valid frame before the link is reported as stale.
+    pub fn stale_timeout(mut self, timeout: Duration) -> Self {
+        self.stale_timeout = timeout;
+        self
+    }
+
+    /// Delay between attempts to re-open a port that failed.
+    pub fn reconnect_interval(mut self, interval: Duration) -> Self {
+        self.reconnect_interval = interval;
+        self
+    }
+
+    /// Interval at which the velocity target is re-sent to the base.
+    pub fn keep_alive_period(mut self, period: Duration) -> Self {
+        self.keep_alive_period = period;
+        self
+    }
+
+    /// Time after which the base is stopped if the velocity target is not refreshed.
+    ///
+    /// This is the timeout of the source used by [`super::CommandSender::set_velocity`].
+    pub fn deadman_timeout(mut self, timeout: Duration) -> Self {
+        self.deadman_timeout = timeout;
+        self
+    }
+
+    /// Ramps the velocity target within the given limits, instead of applying it instantly.
+    ///
+    /// The first step is sent as soon as the target changes, and the ramp continues at the
+    /// keep-alive period.
+    pub fn velocity_smoother(mut self, limits: SmootherLimits) -> Self {
+        self.velocity_smoother = Some(limits);
+        self
+    }
+
+    /// Keeps the gyro heading while a velocity source drives straight, using the given gains.
+    ///
+    /// The heading is captured when a source sets a radius of 0, and the radius is corrected
+    /// at the keep-alive period. See [`crate::control::HeadingHold`].
+    pub fn heading_hold(mut self, gains: PidGains) -> Self {
+        self.heading_hold = Some(gains);
+        self
+    }
+
+    /// Enables the safety supervisor, reacting to the bumper and cliff sensors as given.
+    ///
+    /// The supervisor is disabled by default, so velocity commands are sent as they are.
+    /// There is no default policy, since the right reaction depends on the application.
+    pub fn safety_policy(mut self, policy: SafetyPolicy) -> Self {
+        self.safety_policy = Some(policy);
+        self
+    }
+
+    /// Engages the emergency stop when any of the given buttons on the base is pressed.
+    pub fn emergency_stop_button(mut self, button: Button) -> Self {
+        self.emergency_stop_button = button;
+        self
+    }
+
+    /// Engages the emergency stop when heartbeats stop arriving for longer than `timeout`.
+    ///
+    /// Monitoring starts with the first heartbeat, see [`super::CommandSender::heartbeat`].
+    pub fn heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = Some(timeout);
+        self
+    }
+
+    /// Name used to identify the serial task in log messages.
+    pub fn task_name(mut self, name: impl Into<String>) -> Self {
+        self.task_name = name.into();
+        self
+    }
+
+    /// Opens the serial port at `path` and starts the handler.
+    ///
+    /// If the port fails later on, the handler keeps trying to re-open it, and reports
+    /// [`super::LinkState::Reconnecting`] meanwhile.
+    pub fn open(self, path: impl Into<String>) -> tokio_serial::Result<SerialPortHandler> {
+        let path = path.into();
+        let port = self.open_port(&path)?;
+        Ok(SerialPortHandler::start(self, port, Some(path)))
+    }
+
+    /// Starts the handler on an already opened serial port.
+    ///
+    /// The handler stops if the port fails, since it does not know how to re-open it.
+    pub fn build(self, port: SerialStream) -> SerialPortHandler {
+        SerialPortHandler::start(self, port, None)
+    }
+
+    pub(super) fn open_port(&self, path: &str) -> tokio_serial::Result<SerialStream> {
+        tokio_serial::new(path, self.baud_rate)
+            .timeout(self.timeout)
+            .open_native_async()
+    }
+}
@@ -0,0 +1,186 @@
+use super::{EmergencyStop, EmergencyStopCause, VelocitySource};
+use crate::{
+    control::{SourceId, VelocityMux},
+    tx::{ByteStream, commands::BaseControl},
+};
+use std::{io::ErrorKind, sync::Arc, time::Duration};
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        watch,
+    },
+    time::Instant,
+};
+
+/// Name of the velocity source used by [`CommandSender::set_velocity`].
+const DEFAULT_SOURCE: &str = "default";
+
+/// State shared by all command senders and the serial task.
+pub(crate) struct Shared {
+    pub(crate) mux_tx: watch::Sender<VelocityMux>,
+    pub(crate) active_source_tx: watch::Sender<Option<String>>,
+    pub(crate) estop_tx: watch::Sender<EmergencyStop>,
+    pub(crate) heartbeat_tx: watch::Sender<Option<Instant>>,
+    default_source: SourceId,
+}
+
+impl Shared {
+    pub(crate) fn new(deadman_timeout: Duration) -> Self {
+        let mut mux = VelocityMux::new();
+        let default_source = mux.register(DEFAULT_SOURCE, 0, deadman_timeout);
+        Self {
+            mux_tx: watch::Sender::new(mux),
+            active_source_tx: watch::Sender::new(None),
+            estop_tx: watch::Sender::new(EmergencyStop::Released),
+            heartbeat_tx: watch::Sender::new(None),
+            default_source,
+        }
+    }
+
+    /// Engages the emergency stop, unless it is already engaged.
+    pub(crate) fn engage(&self, cause: EmergencyStopCause) -> bool {
+        self.estop_tx.send_if_modified(|state| {
+            if state.is_engaged() {
+                return false;
+            }
+            *state = EmergencyStop::Engaged(cause);
+            true
+        })
+    }
+
+    pub(crate) fn register(&self, name: &str, priority: u8, timeout: Duration) -> SourceId {
+        let mut id = None;
+        self.mux_tx
+            .send_modify(|mux| id = Some(mux.register(name, priority, timeout)));
+        id.expect("source registered")
+    }
+
+    /// Publishes a velocity from a source of the mux.
+    pub(crate) fn publish(&self, id: SourceId, speed: i16, radius: i16) -> std::io::Result<()> {
+        let velocity = *BaseControl::new(speed, radius);
+        self.check_motion(Some(velocity))?;
+        self.mux_tx
+            .send_modify(|mux| mux.publish(id, velocity, Instant::now()));
+        Ok(())
+    }
+
+    pub(crate) fn release(&self, id: SourceId) {
+        self.mux_tx.send_modify(|mux| mux.release(id));
+    }
+
+    pub(crate) fn unregister(&self, id: SourceId) {
+        self.mux_tx.send_modify(|mux| mux.unregister(id));
+    }
+
+    /// Rejects commands moving the base while the emergency stop is engaged.
+    pub(crate) fn check_motion(&self, velocity: Option<BaseControl>) -> std::io::Result<()> {
+        let moving = velocity.is_some_and(|velocity| velocity.speed() != 0);
+        if moving && self.estop_tx.borrow().is_engaged() {
+            return Err(std::io::Error::new(
+                ErrorKind::PermissionDenied,
+                "emergency stop engaged",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A cheap, cloneable handle for sending commands to the serial task.
+///
+/// Any number of producers can hold a sender, while the [`super::SerialPortHandler`]
+/// controls the lifetime of the serial task.
+#[derive(Clone)]
+pub struct CommandSender {
+    cmd_tx: mpsc::Sender<ByteStream>,
+    shared: Arc<Shared>,
+}
+
+impl CommandSender {
+    pub(crate) fn new(cmd_tx: mpsc::Sender<ByteStream>, shared: Arc<Shared>) -> Self {
+        Self { cmd_tx, shared }
+    }
+
+    /// Queues a command, waiting for room in the command queue if it is full.
+    ///
+    /// Fails with [`ErrorKind::PermissionDenied`] if the command moves the base while the
+    /// emergency stop is engaged.
+    pub async fn send(&self, cmd: ByteStream) -> std::io::Result<()> {
+        self.shared.check_motion(cmd.base_control())?;
+        self.cmd_tx
+            .send(cmd)
+            .await
+            .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "mpsc channel closed"))?;
+        Ok(())
+    }
+
+    /// Queues a command without waiting.
+    ///
+    /// Fails with [`ErrorKind::WouldBlock`] if the command queue is full.
+    pub fn try_send(&self, cmd: ByteStream) -> std::io::Result<()> {
+        self.shared.check_motion(cmd.base_control())?;
+        self.cmd_tx.try_send(cmd).map_err(|e| match e {
+            TrySendError::Full(_) => {
+                std::io::Error::new(ErrorKind::WouldBlock, "mpsc channel full")
+            }
+            TrySendError::Closed(_) => {
+                std::io::Error::new(ErrorKind::BrokenPipe, "mpsc channel closed")
+            }
+        })
+    }
+
+    /// Sets the velocity target of the base, replacing any velocity not yet sent to the port.
+    ///
+    /// Unlike [`Self::send`], this never waits and never queues up stale velocity commands:
+    /// when several producers set the velocity, the latest one wins.
+    ///
+    /// The serial task keeps re-sending the target, so the base keeps moving. If the target
+    /// is not refreshed within the deadman timeout, the base is stopped.
+    ///
+    /// The target is published through the lowest priority source of the velocity mux, so
+    /// any source registered with [`Self::velocity_source`] takes precedence.
+    pub fn set_velocity(&self, speed: i16, radius: i16) -> std::io::Result<()> {
+        self.shared
+            .publish(self.shared.default_source, speed, radius)
+    }
+
+    /// Registers a source with the velocity mux.
+    ///
+    /// The source with the highest priority that has published within its timeout controls
+    /// the base. The source is unregistered when the returned handle and all its clones are
+    /// dropped.
+    pub fn velocity_source(&self, name: &str, priority: u8, timeout: Duration) -> VelocitySource {
+        let id = self.shared.register(name, priority, timeout);
+        VelocitySource::new(id, self.shared.clone())
+    }
+
+    /// Returns a receiver tracking the name of the velocity source controlling the base.
+    pub fn active_velocity_source(&self) -> watch::Receiver<Option<String>> {
+        self.shared.active_source_tx.subscribe()
+    }
+
+    /// Returns the command of the velocity source controlling the base, or `None` if no
+    /// source has published within its timeout.
+    ///
+    /// This is the target before smoothing and safety filtering.
+    pub fn commanded_velocity(&self) -> Option<BaseControl> {
+        self.shared.mux_tx.borrow().command(Instant::now())
+    }
+
+    /// Stops the base, and rejects all motion commands until the emergency stop is reset.
+    pub fn emergency_stop(&self) {
+        self.shared.engage(EmergencyStopCause::Manual);
+    }
+
+    /// Returns a receiver tracking the state of the emergency stop.
+    pub fn emergency_stop_state(&self) -> watch::Receiver<EmergencyStop> {
+        self.shared.estop_tx.subscribe()
+    }
+
+    /// Signals that the remote controller is alive.
+    ///
+    /// With a heartbeat timeout configured, the emergency stop is engaged if heartbeats stop
+    /// arriving. Monitoring starts with the first heartbeat.
+    pub fn heartbeat(&self) {
+        self.shared.heartbeat_tx.send_replace(Some(Instant::now()));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::rx::{BasicSensorData, Button};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// State of the software emergency stop.
+///
+/// Once engaged, the emergency stop stays engaged until it is reset through
+/// [`super::SerialPortHandler::reset_emergency_stop`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmergencyStop {
+    #[default]
+    Released,
+    Engaged(EmergencyStopCause),
+}
+
+impl EmergencyStop {
+    pub fn is_engaged(&self) -> bool {
+        matches!(self, Self::Engaged(_))
+    }
+}
+
+/// What engaged the emergency stop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmergencyStopCause {
+    /// Engaged through [`super::SerialPortHandler::emergency_stop`] or
+    /// [`super::CommandSender::emergency_stop`].
+    Manual,
+    /// One of the configured buttons on the base was pressed.
+    Button(Button),
+    /// No heartbeat was received within the heartbeat timeout.
+    HeartbeatLost,
+}
+
+impl std::fmt::Display for EmergencyStopCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Manual => write!(f, "manual"),
+            Self::Button(button) => write!(f, "{}", button),
+            Self::HeartbeatLost => write!(f, "heartbeat lost"),
+        }
+    }
+}
+
+/// Decides when the serial task engages the emergency stop by itself.
+pub(crate) struct EmergencyStopTriggers {
+    button: Button,
+    heartbeat_timeout: Option<Duration>,
+}
+
+impl EmergencyStopTriggers {
+    pub(crate) fn new(button: Button, heartbeat_timeout: Option<Duration>) -> Self {
+        Self {
+            button,
+            heartbeat_timeout,
+        }
+    }
+
+    /// Returns the cause if one of the configured buttons is pressed.
+    pub(crate) fn button(&self, bsd: &BasicSensorData) -> Option<EmergencyStopCause> {
+        let pressed = bsd.button & self.button;
+        (!pressed.is_empty()).then_some(EmergencyStopCause::Button(pressed))
+    }
+
+    /// Returns the cause if heartbeats have been received, but the last one is too old.
+    pub(crate) fn heartbeat(
+        &self,
+        last_heartbeat: Option<Instant>,
+        now: Instant,
+    ) -> Option<EmergencyStopCause> {
+        let timeout = self.heartbeat_timeout?;
+        let last_heartbeat = last_heartbeat?;
+        (now.duration_since(last_heartbeat) > timeout).then_some(EmergencyStopCause::HeartbeatLost)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_button() {
+        let triggers = EmergencyStopTriggers::new(Button::B0 | Button::B2, None);
+        let pressed = |button| BasicSensorData {
+            button,
+            ..Default::default()
+        };
+        assert_eq!(triggers.button(&pressed(Button::empty())), None);
+        assert_eq!(triggers.button(&pressed(Button::B1)), None);
+        assert_eq!(
+            triggers.button(&pressed(Button::B1 | Button::B2)),
+            Some(EmergencyStopCause::Button(Button::B2))
+        );
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let triggers = EmergencyStopTriggers::new(Button::empty(), Some(Duration::from_secs(1)));
+        // Heartbeat monitoring starts with the first heartbeat
+        assert_eq!(triggers.heartbeat(None, at(5000)), None);
+        assert_eq!(triggers.heartbeat(Some(start), at(1000)), None);
+        assert_eq!(
+            triggers.heartbeat(Some(start), at(1001)),
+            Some(EmergencyStopCause::HeartbeatLost)
+        );
+
+        let triggers = EmergencyStopTriggers::new(Button::empty(), None);
+        assert_eq!(triggers.heartbeat(Some(start), at(5000)), None);
+    }
+}
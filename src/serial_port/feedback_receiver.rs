@@ -0,0 +1,126 @@
+use crate::rx::Feedback;
+use log::debug;
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+
+/// How a feedback subscriber catches up after falling behind the feedback channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Continue with the oldest frame still held by the channel. The frames that were
+    /// overwritten are lost, the remaining frames are delivered in order.
+    #[default]
+    DropOldest,
+    /// Discard every queued frame and continue with the most recent one.
+    SkipToLatest,
+}
+
+/// Receives feedback frames from a [`super::SerialPortHandler`].
+///
+/// Falling behind is handled according to the [`LagPolicy`] of the handler, so `recv` only
+/// fails when the serial task has stopped.
+pub struct FeedbackReceiver {
+    rx: broadcast::Receiver<Feedback>,
+    lag_policy: LagPolicy,
+}
+
+impl FeedbackReceiver {
+    pub(crate) fn new(rx: broadcast::Receiver<Feedback>, lag_policy: LagPolicy) -> Self {
+        Self { rx, lag_policy }
+    }
+
+    /// Waits for the next feedback frame.
+    ///
+    /// Returns [`RecvError::Closed`] when the serial task has stopped.
+    pub async fn recv(&mut self) -> Result<Feedback, RecvError> {
+        loop {
+            match self.rx.recv().await {
+                Ok(feedback) => return Ok(feedback),
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Feedback receiver lagged, {} frames dropped", skipped);
+                    if self.lag_policy == LagPolicy::SkipToLatest
+                        && let Some(feedback) = self.latest()
+                    {
+                        return Ok(feedback);
+                    }
+                }
+                Err(RecvError::Closed) => return Err(RecvError::Closed),
+            }
+        }
+    }
+
+    /// Returns the next feedback frame if one is queued, without waiting.
+    pub fn try_recv(&mut self) -> Result<Feedback, TryRecvError> {
+        loop {
+            match self.rx.try_recv() {
+                Err(TryRecvError::Lagged(skipped)) => {
+                    debug!("Feedback receiver lagged, {} frames dropped", skipped);
+                    if self.lag_policy == LagPolicy::SkipToLatest
+                        && let Some(feedback) = self.latest()
+                    {
+                        return Ok(feedback);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Creates a new receiver starting from the next frame, with the same lag policy.
+    pub fn resubscribe(&self) -> Self {
+        Self::new(self.rx.resubscribe(), self.lag_policy)
+    }
+
+    /// Drains the channel and returns the newest queued frame, if any.
+    fn latest(&mut self) -> Option<Feedback> {
+        let mut latest = None;
+        loop {
+            match self.rx.try_recv() {
+                Ok(feedback) => latest = Some(feedback),
+                Err(TryRecvError::Lagged(_)) => {}
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return latest,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::inertial_frame;
+
+    fn angle(feedback: Feedback) -> f32 {
+        feedback.inertial_sensor.unwrap().angle
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_continues_with_oldest_retained() {
+        let (tx, rx) = broadcast::channel(2);
+        let mut rx = FeedbackReceiver::new(rx, LagPolicy::DropOldest);
+        for i in 0..5 {
+            tx.send(inertial_frame(i as f32)).unwrap();
+        }
+        assert_eq!(angle(rx.recv().await.unwrap()), 3.0);
+        assert_eq!(angle(rx.recv().await.unwrap()), 4.0);
+    }
+
+    #[tokio::test]
+    async fn test_skip_to_latest() {
+        let (tx, rx) = broadcast::channel(2);
+        let mut rx = FeedbackReceiver::new(rx, LagPolicy::SkipToLatest);
+        for i in 0..5 {
+            tx.send(inertial_frame(i as f32)).unwrap();
+        }
+        assert_eq!(angle(rx.recv().await.unwrap()), 4.0);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[tokio::test]
+    async fn test_closed() {
+        let (tx, rx) = broadcast::channel(2);
+        let mut rx = FeedbackReceiver::new(rx, LagPolicy::DropOldest);
+        drop(tx);
+        assert_eq!(rx.recv().await, Err(RecvError::Closed));
+    }
+}
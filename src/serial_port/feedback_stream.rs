@@ -0,0 +1,165 @@
+use super::FeedbackReceiver;
+use crate::rx::{BasicSensorData, DockingIr, Feedback, Gyro, InertialSensor};
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::ReusableBoxFuture;
+
+/// A [`Stream`] of feedback frames from a [`super::SerialPortHandler`].
+///
+/// The stream ends when the serial task stops.
+pub struct FeedbackStream {
+    inner: ReusableBoxFuture<'static, (Result<Feedback, RecvError>, FeedbackReceiver)>,
+}
+
+async fn recv(mut rx: FeedbackReceiver) -> (Result<Feedback, RecvError>, FeedbackReceiver) {
+    let result = rx.recv().await;
+    (result, rx)
+}
+
+impl FeedbackStream {
+    pub fn new(rx: FeedbackReceiver) -> Self {
+        Self {
+            inner: ReusableBoxFuture::new(recv(rx)),
+        }
+    }
+}
+
+impl From<FeedbackReceiver> for FeedbackStream {
+    fn from(rx: FeedbackReceiver) -> Self {
+        Self::new(rx)
+    }
+}
+
+impl Stream for FeedbackStream {
+    type Item = Feedback;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let (result, rx) = ready!(self.inner.poll(cx));
+        self.inner.set(recv(rx));
+        Poll::Ready(result.ok())
+    }
+}
+
+/// Adapters for streams of feedback frames, such as [`FeedbackStream`].
+///
+/// ```no_run
+/// # use kobuki_interface::serial_port::{FeedbackStreamExt, SerialPortHandler};
+/// # use tokio_stream::StreamExt;
+/// # async fn example(handler: SerialPortHandler) {
+/// let mut angles = handler.feedback_stream().decimate(10).inertial();
+/// while let Some(inertial) = angles.next().await {
+///     println!("Angle: {}", inertial.angle);
+/// }
+/// # }
+/// ```
+pub trait FeedbackStreamExt: Stream<Item = Feedback> {
+    /// Yields the basic sensor data of the frames that contain it.
+    fn basic_sensor_data(self) -> impl Stream<Item = BasicSensorData>
+    where
+        Self: Sized,
+    {
+        self.filter_map(|feedback| feedback.basic_sensor_data)
+    }
+
+    /// Yields the docking IR signals of the frames that contain them.
+    fn docking_ir(self) -> impl Stream<Item = DockingIr>
+    where
+        Self: Sized,
+    {
+        self.filter_map(|feedback| feedback.docking_ir)
+    }
+
+    /// Yields the inertial sensor data of the frames that contain it.
+    fn inertial(self) -> impl Stream<Item = InertialSensor>
+    where
+        Self: Sized,
+    {
+        self.filter_map(|feedback| feedback.inertial_sensor)
+    }
+
+    /// Yields the raw gyro samples of the frames that contain them.
+    fn gyro(self) -> impl Stream<Item = Gyro>
+    where
+        Self: Sized,
+    {
+        self.filter_map(|feedback| feedback.gyro)
+    }
+
+    /// Yields every `n`th frame, starting with the first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    fn decimate(self, n: usize) -> impl Stream<Item = Feedback>
+    where
+        Self: Sized,
+    {
+        assert!(n > 0, "decimation factor must be positive");
+        let mut count = 0;
+        self.filter(move |_| {
+            let keep = count == 0;
+            count = (count + 1) % n;
+            keep
+        })
+    }
+}
+
+impl<S: Stream<Item = Feedback>> FeedbackStreamExt for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{serial_port::LagPolicy, test_support::inertial_frame};
+    use tokio::sync::broadcast;
+
+    fn stream_of(frames: Vec<Feedback>) -> FeedbackStream {
+        let (tx, rx) = broadcast::channel(frames.len().max(1));
+        for frame in frames {
+            tx.send(frame).unwrap();
+        }
+        FeedbackStream::new(FeedbackReceiver::new(rx, LagPolicy::DropOldest))
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_when_closed() {
+        let frames = vec![inertial_frame(1.0), inertial_frame(2.0)];
+        let result: Vec<_> = stream_of(frames.clone()).collect().await;
+        assert_eq!(result, frames);
+    }
+
+    #[tokio::test]
+    async fn test_typed_filter() {
+        let mut docking = Feedback::new();
+        docking.docking_ir = Some(DockingIr::default());
+        let frames = vec![inertial_frame(1.0), docking, inertial_frame(2.0)];
+
+        let angles: Vec<_> = stream_of(frames.clone())
+            .inertial()
+            .map(|inertial| inertial.angle)
+            .collect()
+            .await;
+        assert_eq!(angles, vec![1.0, 2.0]);
+
+        let docking_ir: Vec<_> = stream_of(frames.clone()).docking_ir().collect().await;
+        assert_eq!(docking_ir, vec![DockingIr::default()]);
+
+        let basic_sensor_data: Vec<_> = stream_of(frames).basic_sensor_data().collect().await;
+        assert!(basic_sensor_data.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_decimate() {
+        let frames = (0..7).map(|i| inertial_frame(i as f32)).collect();
+        let angles: Vec<_> = stream_of(frames)
+            .decimate(3)
+            .inertial()
+            .map(|inertial| inertial.angle)
+            .collect()
+            .await;
+        assert_eq!(angles, vec![0.0, 3.0, 6.0]);
+    }
+}
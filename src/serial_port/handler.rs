@@ -1,87 +1,328 @@
+use super::{
+    CommandSender, EmergencyStop, FeedbackReceiver, FeedbackStream, LagPolicy, LinkMonitor,
+    LinkState, Motion, SerialPortHandlerBuilder, Shared, VelocitySource,
+};
 use crate::{
-    rx::{Feedback, FeedbackDecoder},
-    tx::{ByteStream, commands},
+    control::{SafetyEvent, VelocityMux},
+    rx::{Feedback, FeedbackDecoder, RobotState},
+    tx::{ByteStream, commands, commands::BaseControl},
 };
-use log::error;
-use std::io::ErrorKind;
+use bytes::Buf;
+use log::{error, info, warn};
+use std::{sync::Arc, time::Duration};
 use tokio::{
     io::AsyncReadExt,
     signal,
-    sync::{broadcast, mpsc},
+    sync::{broadcast, mpsc, watch},
     task::JoinHandle,
+    time::{Instant, MissedTickBehavior, interval, sleep, sleep_until},
 };
 use tokio_serial::SerialStream;
-use tokio_util::codec::Decoder;
+use tokio_util::{codec::Decoder, sync::CancellationToken};
+
+/// Number of safety events buffered for each subscriber.
+const SAFETY_EVENT_CAPACITY: usize = 16;
 
 pub struct SerialPortHandler {
-    cmd_tx: mpsc::Sender<ByteStream>,
+    commands: CommandSender,
     feedback_rx: broadcast::Receiver<Feedback>,
-    _serial_task: SerialPortTask,
+    lag_policy: LagPolicy,
+    link_rx: watch::Receiver<LinkState>,
+    state_rx: watch::Receiver<RobotState>,
+    safety_tx: broadcast::Sender<SafetyEvent>,
+    shared: Arc<Shared>,
+    serial_task: SerialPortTask,
 }
 
 impl SerialPortHandler {
+    /// Starts a handler on `port` using the default configuration.
+    ///
+    /// The handler stops if the port fails, since it does not know how to re-open it.
     pub fn new(port: SerialStream) -> Self {
-        let (feedback_tx, feedback_rx) = broadcast::channel(10);
-        let (cmd_tx, cmd_rx) = mpsc::channel(10);
-        let serial_task = SerialPortTask::new(port, cmd_rx, feedback_tx);
+        Self::builder().build(port)
+    }
+
+    /// Opens the serial port at `path` using the default configuration.
+    ///
+    /// If the port fails later on, the handler keeps trying to re-open it, and reports
+    /// [`LinkState::Reconnecting`] meanwhile.
+    pub fn open(path: impl Into<String>) -> tokio_serial::Result<Self> {
+        Self::builder().open(path)
+    }
+
+    pub fn builder() -> SerialPortHandlerBuilder {
+        SerialPortHandlerBuilder::new()
+    }
+
+    pub(super) fn start(
+        config: SerialPortHandlerBuilder,
+        port: SerialStream,
+        path: Option<String>,
+    ) -> Self {
+        let (feedback_tx, feedback_rx) = broadcast::channel(config.feedback_capacity);
+        let (cmd_tx, cmd_rx) = mpsc::channel(config.command_capacity);
+        let shared = Arc::new(Shared::new(config.deadman_timeout));
+        let (link_tx, link_rx) = watch::channel(LinkState::Connecting);
+        let (state_tx, state_rx) = watch::channel(RobotState::new());
+        let (safety_tx, _) = broadcast::channel(SAFETY_EVENT_CAPACITY);
+        let lag_policy = config.lag_policy;
+        let link = LinkMonitor::new(link_tx, config.stale_timeout);
+        let serial_task = SerialPortTask::new(
+            TaskContext {
+                config,
+                path,
+                cmd_rx,
+                mux_rx: shared.mux_tx.subscribe(),
+                estop_rx: shared.estop_tx.subscribe(),
+                shared: shared.clone(),
+                feedback_tx,
+                link,
+                state_tx,
+                safety_tx: safety_tx.clone(),
+                shutdown: CancellationToken::new(),
+            },
+            port,
+        );
         Self {
-            cmd_tx,
+            commands: CommandSender::new(cmd_tx, shared.clone()),
+            shared,
             feedback_rx,
-            _serial_task: serial_task,
+            lag_policy,
+            link_rx,
+            state_rx,
+            safety_tx,
+            serial_task,
         }
     }
 
     pub async fn send_command(&self, cmd: ByteStream) -> std::io::Result<()> {
-        self.cmd_tx
-            .send(cmd)
-            .await
-            .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "mpsc channel closed"))?;
-        Ok(())
+        self.commands.send(cmd).await
+    }
+
+    /// Sets the velocity target of the base, see [`CommandSender::set_velocity`].
+    pub fn set_velocity(&self, speed: i16, radius: i16) -> std::io::Result<()> {
+        self.commands.set_velocity(speed, radius)
+    }
+
+    /// Registers a source with the velocity mux, see [`CommandSender::velocity_source`].
+    pub fn velocity_source(&self, name: &str, priority: u8, timeout: Duration) -> VelocitySource {
+        self.commands.velocity_source(name, priority, timeout)
+    }
+
+    /// Releases every source of the velocity mux, until they publish again.
+    pub(crate) fn release_velocity_sources(&self) {
+        self.shared.mux_tx.send_modify(|mux| mux.release_all());
+    }
+
+    /// Returns a receiver tracking the name of the velocity source controlling the base.
+    pub fn active_velocity_source(&self) -> watch::Receiver<Option<String>> {
+        self.commands.active_velocity_source()
+    }
+
+    /// Returns the command of the velocity source controlling the base, see
+    /// [`CommandSender::commanded_velocity`].
+    pub fn commanded_velocity(&self) -> Option<BaseControl> {
+        self.commands.commanded_velocity()
+    }
+
+    /// Stops the base, and rejects all motion commands from every producer until
+    /// [`Self::reset_emergency_stop`] is called.
+    pub fn emergency_stop(&self) {
+        self.commands.emergency_stop();
+    }
+
+    /// Releases the emergency stop. The base stays stopped until a new velocity is set.
+    pub fn reset_emergency_stop(&self) {
+        // Restart heartbeat monitoring, or a lost heartbeat would engage it again right away
+        self.shared.heartbeat_tx.send_replace(None);
+        self.shared.estop_tx.send_replace(EmergencyStop::Released);
+    }
+
+    /// Returns a receiver tracking the state of the emergency stop.
+    pub fn emergency_stop_state(&self) -> watch::Receiver<EmergencyStop> {
+        self.commands.emergency_stop_state()
+    }
+
+    /// Signals that the remote controller is alive, see [`CommandSender::heartbeat`].
+    pub fn heartbeat(&self) {
+        self.commands.heartbeat();
+    }
+
+    /// Returns a cloneable handle for sending commands from other tasks.
+    pub fn command_sender(&self) -> CommandSender {
+        self.commands.clone()
     }
 
     pub fn subscribe(&self) -> broadcast::Receiver<Feedback> {
         self.feedback_rx.resubscribe()
     }
+
+    /// Like [`Self::subscribe`], but catching up according to the configured [`LagPolicy`]
+    /// when the receiver falls behind.
+    pub fn feedback_receiver(&self) -> FeedbackReceiver {
+        FeedbackReceiver::new(self.subscribe(), self.lag_policy)
+    }
+
+    /// Returns the feedback frames as a [`tokio_stream::Stream`].
+    ///
+    /// See [`super::FeedbackStreamExt`] for adapters selecting specific sub-payloads.
+    pub fn feedback_stream(&self) -> FeedbackStream {
+        FeedbackStream::new(self.feedback_receiver())
+    }
+
+    /// Returns a receiver tracking the health of the serial link.
+    ///
+    /// The state changes to [`LinkState::Connected`] when valid frames arrive, to
+    /// [`LinkState::Stale`] when they stop arriving for the stale timeout of the builder, to
+    /// [`LinkState::Reconnecting`] while a failed port is re-opened, and to
+    /// [`LinkState::Disconnected`] when the serial task terminates.
+    pub fn connection_state(&self) -> watch::Receiver<LinkState> {
+        self.link_rx.clone()
+    }
+
+    /// Returns a receiver holding the latest value of every feedback sub-payload.
+    ///
+    /// Unlike [`Self::feedback_receiver`], a slow consumer never lags behind, it simply observes the
+    /// most recent state when it reads.
+    pub fn robot_state(&self) -> watch::Receiver<RobotState> {
+        self.state_rx.clone()
+    }
+
+    /// Returns a receiver of the interventions of the safety supervisor.
+    pub fn safety_events(&self) -> broadcast::Receiver<SafetyEvent> {
+        self.safety_tx.subscribe()
+    }
+
+    /// Stops the base and the serial task, waiting for the task to terminate.
+    ///
+    /// Dropping the handler aborts the serial task instead, without stopping the base.
+    pub async fn shutdown(mut self) {
+        self.serial_task.shutdown().await;
+    }
+}
+
+/// Everything the serial task owns besides the port itself.
+struct TaskContext {
+    config: SerialPortHandlerBuilder,
+    /// Path of the port, if the task is able to re-open it.
+    path: Option<String>,
+    cmd_rx: mpsc::Receiver<ByteStream>,
+    mux_rx: watch::Receiver<VelocityMux>,
+    estop_rx: watch::Receiver<EmergencyStop>,
+    shared: Arc<Shared>,
+    feedback_tx: broadcast::Sender<Feedback>,
+    link: LinkMonitor,
+    state_tx: watch::Sender<RobotState>,
+    safety_tx: broadcast::Sender<SafetyEvent>,
+    shutdown: CancellationToken,
 }
 
 struct SerialPortTask {
     task: JoinHandle<()>,
+    shutdown: CancellationToken,
 }
 
 impl SerialPortTask {
-    fn new(
-        port: SerialStream,
-        cmd_rx: mpsc::Receiver<ByteStream>,
-        feedback_tx: broadcast::Sender<Feedback>,
-    ) -> Self {
+    fn new(mut ctx: TaskContext, port: SerialStream) -> Self {
+        let shutdown = ctx.shutdown.clone();
         let task = tokio::spawn(async move {
-            if let Err(e) = Self::run(port, cmd_rx, feedback_tx).await {
-                error!("Error handling serial port: {:?}", e);
+            let mut port = port;
+            loop {
+                match Self::run(port, &mut ctx).await {
+                    Ok(()) => break,
+                    Err(e) => error!(
+                        "{}: Error handling serial port: {:?}",
+                        ctx.config.task_name, e
+                    ),
+                }
+                match Self::reconnect(&mut ctx).await {
+                    Some(new_port) => port = new_port,
+                    None => break,
+                }
             }
+            ctx.link.disconnected();
         });
 
-        Self { task }
+        Self { task, shutdown }
     }
 
-    pub async fn run(
-        mut port: SerialStream,
-        mut cmd_rx: mpsc::Receiver<ByteStream>,
-        feedback_tx: broadcast::Sender<Feedback>,
-    ) -> std::io::Result<()> {
+    async fn shutdown(&mut self) {
+        self.shutdown.cancel();
+        (&mut self.task).await.ok();
+    }
+
+    pub async fn run(mut port: SerialStream, ctx: &mut TaskContext) -> std::io::Result<()> {
         let mut decoder = FeedbackDecoder {};
         let mut buf = bytes::BytesMut::new();
+        let mut motion = Motion::new(&ctx.config, ctx.shared.clone(), ctx.safety_tx.clone());
+        let mut keep_alive_interval = interval(ctx.config.keep_alive_period);
+        keep_alive_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ctx.link.connecting();
 
         loop {
+            let stale_deadline = ctx.link.stale_deadline();
             tokio::select! {
-                cmd = cmd_rx.recv() => {
+                cmd = ctx.cmd_rx.recv() => {
+                    let cmd = cmd.map(|cmd| motion.filter_stream(cmd, Instant::now()));
                     Self::handle_command(cmd, &mut port).await?;
                 }
+                Ok(()) = ctx.mux_rx.changed() => {
+                    ctx.mux_rx.mark_unchanged();
+                    if let Some(velocity) = motion.set_target(Instant::now()) {
+                        Self::write_velocity(velocity, &mut port).await?;
+                    }
+                }
+                Ok(()) = ctx.estop_rx.changed() => {
+                    let state = *ctx.estop_rx.borrow_and_update();
+                    if let EmergencyStop::Engaged(cause) = state {
+                        warn!("{}: Emergency stop engaged ({})", ctx.config.task_name, cause);
+                        motion.emergency_stop();
+                        Self::write_velocity(*BaseControl::new(0, 0), &mut port).await?;
+                    } else {
+                        info!("{}: Emergency stop released", ctx.config.task_name);
+                    }
+                }
+                _ = keep_alive_interval.tick() => {
+                    if let Some(velocity) = motion.tick(Instant::now()) {
+                        Self::write_velocity(velocity, &mut port).await?;
+                    }
+                }
                 size = port.read_buf(&mut buf) => {
-                    Self::handle_read(size?, &mut buf, &mut decoder, &feedback_tx)?;
+                    let size = size?;
+                    let task_name = &ctx.config.task_name;
+                    let frames = Self::handle_read(size, &mut buf, &mut decoder, task_name);
+                    let now = Instant::now();
+                    if !frames.is_empty() {
+                        ctx.link.received(now);
+                    }
+                    for frame in frames {
+                        let reaction = motion.feedback(&frame, now);
+                        Self::publish(frame, ctx);
+                        if let Some(velocity) = reaction {
+                            Self::write_velocity(velocity, &mut port).await?;
+                        }
+                    }
+                    if size == 0 {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::ConnectionAborted,
+                            "Connection closed by peer",
+                        ));
+                    }
+                }
+                _ = sleep_until(stale_deadline.unwrap_or_else(Instant::now)), if stale_deadline.is_some() => {
+                    if ctx.link.check_stale(Instant::now()) {
+                        warn!(
+                            "{}: No feedback received for {:?}",
+                            ctx.config.task_name, ctx.config.stale_timeout
+                        );
+                    }
                 }
                 _ = signal::ctrl_c() => {
                     break;
                 }
+                _ = ctx.shutdown.cancelled() => {
+                    break;
+                }
             }
         }
 
@@ -90,6 +331,30 @@ impl SerialPortTask {
         Ok(())
     }
 
+    /// Re-opens the port after a failure. Returns `None` if the port cannot be re-opened
+    /// because the path is unknown, or if the process is interrupted while waiting.
+    async fn reconnect(ctx: &mut TaskContext) -> Option<SerialStream> {
+        let path = ctx.path.clone()?;
+        ctx.link.reconnecting();
+        loop {
+            tokio::select! {
+                _ = sleep(ctx.config.reconnect_interval) => {}
+                _ = signal::ctrl_c() => return None,
+                _ = ctx.shutdown.cancelled() => return None,
+            }
+            match ctx.config.open_port(&path) {
+                Ok(port) => {
+                    info!("{}: Re-opened serial port {}", ctx.config.task_name, path);
+                    return Some(port);
+                }
+                Err(e) => warn!(
+                    "{}: Unable to re-open serial port {}: {}",
+                    ctx.config.task_name, path, e
+                ),
+            }
+        }
+    }
+
     async fn handle_command(
         cmd: Option<ByteStream>,
         port: &mut SerialStream,
@@ -110,26 +375,45 @@ impl SerialPortTask {
         Ok(())
     }
 
+    async fn write_velocity(velocity: BaseControl, port: &mut SerialStream) -> std::io::Result<()> {
+        let cmd = ByteStream::builder().subpayload(Box::new(velocity));
+        Self::handle_command(Some(cmd), port).await
+    }
+
+    /// Decodes and returns all complete frames in `buf`.
+    ///
+    /// A read of size 0 means the port was closed, so whatever is left in `buf` is decoded.
+    /// A frame that fails to decode is logged and skipped, so a corrupted frame does not
+    /// take the link down.
     fn handle_read(
         size: usize,
         buf: &mut bytes::BytesMut,
         decoder: &mut FeedbackDecoder,
-        feedback_tx: &broadcast::Sender<Feedback>,
-    ) -> std::io::Result<()> {
-        if size == 0 {
-            while let Some(frame) = decoder.decode_eof(buf)? {
-                feedback_tx.send(frame).ok(); // send will give error if no subscribers - ignore errors
+        task_name: &str,
+    ) -> Vec<Feedback> {
+        let mut frames = Vec::new();
+        loop {
+            let decoded = if size == 0 {
+                decoder.decode_eof(buf)
+            } else {
+                decoder.decode(buf)
+            };
+            match decoded {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => return frames,
+                Err(e) => {
+                    warn!("{}: Skipping malformed feedback frame: {}", task_name, e);
+                    // The decoder stops at the header of the bad frame, so skip past it
+                    buf.advance(buf.len().min(2));
+                }
             }
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::ConnectionAborted,
-                "Connection closed by peer",
-            ));
         }
+    }
 
-        while let Some(frame) = decoder.decode(buf)? {
-            feedback_tx.send(frame).ok(); // send will give error if no subscribers - ignore errors
-        }
-        Ok(())
+    fn publish(frame: Feedback, ctx: &TaskContext) {
+        ctx.state_tx
+            .send_modify(|state| state.update(&frame, Instant::now()));
+        ctx.feedback_tx.send(frame).ok(); // send will give error if no subscribers - ignore errors
     }
 }
 
@@ -138,3 +422,35 @@ impl Drop for SerialPortTask {
         self.task.abort();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rx::IrSignal;
+
+    /// A docking IR frame, with the right sensor reading `right`.
+    fn docking_ir_frame(right: u8, checksum: u8) -> [u8; 9] {
+        [0xaa, 0x55, 0x05, 0x03, 0x03, right, 0x00, 0x00, checksum]
+    }
+
+    #[test]
+    fn test_skips_malformed_frame() {
+        let mut decoder = FeedbackDecoder {};
+        let mut buf = bytes::BytesMut::new();
+        // A bad checksum, then an unknown sub-payload, then a good frame
+        buf.extend_from_slice(&docking_ir_frame(0x01, 0x00));
+        buf.extend_from_slice(&[0xaa, 0x55, 0x02, 0x7f, 0x00, 0x7d]);
+        buf.extend_from_slice(&docking_ir_frame(0x01, 0x04));
+        let frames = SerialPortTask::handle_read(buf.len(), &mut buf, &mut decoder, "test");
+        assert_eq!(frames.len(), 1);
+        let ir = frames[0].docking_ir.as_ref().unwrap();
+        assert_eq!(ir.right, IrSignal::NEAR_LEFT);
+        assert!(buf.is_empty());
+
+        // A truncated frame at the end of the stream is dropped too
+        buf.extend_from_slice(&docking_ir_frame(0x01, 0x04)[..5]);
+        let frames = SerialPortTask::handle_read(0, &mut buf, &mut decoder, "test");
+        assert!(frames.is_empty());
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{
+    control::{SmootherLimits, Twist, VelocitySmoother},
+    tx::commands::BaseControl,
+};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Tracks the velocity target selected by the mux, and decides what to send to the base.
+///
+/// The target is re-sent on every tick until the mux has no active source left, at which
+/// point a single zero velocity command is sent. With a smoother, the velocity is ramped
+/// towards the target on every tick and on every change of the target instead, by as much as
+/// the time since the previous step allows.
+pub(crate) struct KeepAlive {
+    active: bool,
+    period: Duration,
+    smoothing: Option<Smoothing>,
+}
+
+struct Smoothing {
+    smoother: VelocitySmoother,
+    /// The target being ramped towards, kept until the base has come to a stop.
+    target: Option<BaseControl>,
+    last_step: Option<Instant>,
+}
+
+impl Smoothing {
+    /// Ramps towards the target, and returns the command to send if there is one.
+    fn step(&mut self, now: Instant, period: Duration) -> Option<BaseControl> {
+        let target = self.target?;
+        let dt = self
+            .last_step
+            .replace(now)
+            .map_or(period, |last| now.duration_since(last).min(period));
+        let cmd = self.smoother.step_command(target, dt);
+        if cmd == target && target.speed() == 0 {
+            self.target = None;
+        }
+        Some(cmd)
+    }
+}
+
+impl KeepAlive {
+    pub(crate) fn new(period: Duration, smoother: Option<SmootherLimits>) -> Self {
+        Self {
+            active: false,
+            period,
+            smoothing: smoother.map(|limits| Smoothing {
+                smoother: VelocitySmoother::new(limits),
+                target: None,
+                last_step: None,
+            }),
+        }
+    }
+
+    /// Updates the target after a change in the mux or on a keep-alive tick, and returns the
+    /// command to send right away, if any.
+    pub(crate) fn update(
+        &mut self,
+        target: Option<BaseControl>,
+        now: Instant,
+    ) -> Option<BaseControl> {
+        let target = self.resolve(target);
+        let Some(smoothing) = &mut self.smoothing else {
+            return target;
+        };
+        if target.is_some() {
+            smoothing.target = target;
+        }
+        smoothing.step(now, self.period)
+    }
+
+    /// Forgets the target, and tells the smoother that the base was stopped.
+    pub(crate) fn clear(&mut self) {
+        self.active = false;
+        if let Some(smoothing) = &mut self.smoothing {
+            smoothing.target = None;
+        }
+        self.stopped();
+    }
+
+    /// Tells the smoother that the base was stopped by other means.
+    pub(crate) fn stopped(&mut self) {
+        if let Some(smoothing) = &mut self.smoothing {
+            smoothing.smoother.reset(Twist::zero());
+        }
+    }
+
+    /// Returns true if a source was in control on the last update.
+    pub(crate) fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Turns the loss of the last active source into a single zero velocity command.
+    fn resolve(&mut self, target: Option<BaseControl>) -> Option<BaseControl> {
+        if target.is_some() {
+            self.active = true;
+            return target;
+        }
+        std::mem::take(&mut self.active).then(|| *BaseControl::new(0, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_no_target() {
+        let mut keep_alive = KeepAlive::new(PERIOD, None);
+        assert_eq!(keep_alive.update(None, Instant::now()), None);
+    }
+
+    #[test]
+    fn test_resend_until_released() {
+        let now = Instant::now();
+        let mut keep_alive = KeepAlive::new(PERIOD, None);
+        let target = *BaseControl::new(100, 0);
+        assert_eq!(keep_alive.update(Some(target), now), Some(target));
+        assert_eq!(keep_alive.update(Some(target), now), Some(target));
+        assert!(keep_alive.is_active());
+        assert_eq!(keep_alive.update(None, now), Some(*BaseControl::new(0, 0)));
+        assert!(!keep_alive.is_active());
+        assert_eq!(keep_alive.update(None, now), None);
+    }
+
+    #[test]
+    fn test_release_stops_right_away() {
+        let now = Instant::now();
+        let mut keep_alive = KeepAlive::new(PERIOD, None);
+        keep_alive.update(Some(*BaseControl::new(100, 0)), now);
+        assert_eq!(keep_alive.update(None, now), Some(*BaseControl::new(0, 0)));
+        assert_eq!(keep_alive.update(None, now), None);
+    }
+
+    #[test]
+    fn test_smoothed_until_stopped() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let limits = SmootherLimits {
+            linear_acceleration: 1.0,
+            linear_stop_deceleration: 1.0,
+            linear_jerk: f32::INFINITY,
+            ..Default::default()
+        };
+        let mut keep_alive = KeepAlive::new(PERIOD, Some(limits));
+        let target = Some(*BaseControl::new(200, 0));
+        let speed = |cmd: Option<BaseControl>| cmd.map(|cmd| cmd.speed());
+        // The first step is sent right away
+        assert_eq!(speed(keep_alive.update(target, at(0))), Some(100));
+        assert_eq!(speed(keep_alive.update(target, at(100))), Some(200));
+        assert_eq!(speed(keep_alive.update(target, at(200))), Some(200));
+        // Losing the source starts slowing down right away, by the time since the last step
+        assert_eq!(speed(keep_alive.update(None, at(250))), Some(150));
+        let speeds: Vec<_> = [300, 400, 500, 600]
+            .into_iter()
+            .map(|ms| speed(keep_alive.update(None, at(ms))))
+            .collect();
+        assert_eq!(speeds, [Some(100), Some(0), None, None]);
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::Duration;
+use tokio::{sync::watch, time::Instant};
+
+/// Health of the serial link to the Kobuki base, as seen from the received feedback frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LinkState {
+    /// The port is open, but no valid feedback frame has been received yet.
+    #[default]
+    Connecting,
+    /// Valid feedback frames are arriving.
+    Connected,
+    /// No valid feedback frame has been received within the stale timeout.
+    Stale,
+    /// The serial task has stopped and no more feedback will arrive.
+    Disconnected,
+    /// The port failed and is being re-opened.
+    Reconnecting,
+}
+
+impl std::fmt::Display for LinkState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self {
+            Self::Connecting => "connecting",
+            Self::Connected => "connected",
+            Self::Stale => "stale",
+            Self::Disconnected => "disconnected",
+            Self::Reconnecting => "reconnecting",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+/// Drives the [`LinkState`] published by the serial task.
+pub(crate) struct LinkMonitor {
+    tx: watch::Sender<LinkState>,
+    stale_timeout: Duration,
+    deadline: Instant,
+}
+
+impl LinkMonitor {
+    pub(crate) fn new(tx: watch::Sender<LinkState>, stale_timeout: Duration) -> Self {
+        Self {
+            tx,
+            stale_timeout,
+            deadline: Instant::now() + stale_timeout,
+        }
+    }
+
+    pub(crate) fn state(&self) -> LinkState {
+        *self.tx.borrow()
+    }
+
+    /// The port was opened, and no frame has been received from it yet.
+    pub(crate) fn connecting(&mut self) {
+        self.set(LinkState::Connecting);
+    }
+
+    /// Valid frames were received at `now`.
+    pub(crate) fn received(&mut self, now: Instant) {
+        self.deadline = now + self.stale_timeout;
+        self.set(LinkState::Connected);
+    }
+
+    /// Returns when the link goes stale, unless a frame arrives before. Only a connected
+    /// link goes stale.
+    pub(crate) fn stale_deadline(&self) -> Option<Instant> {
+        (self.state() == LinkState::Connected).then_some(self.deadline)
+    }
+
+    /// Marks the link as stale if no frame has arrived within the stale timeout. Returns true
+    /// if the link just went stale.
+    pub(crate) fn check_stale(&mut self, now: Instant) -> bool {
+        self.stale_deadline()
+            .is_some_and(|deadline| now >= deadline)
+            && self.set(LinkState::Stale)
+    }
+
+    /// The port failed, and is being re-opened.
+    pub(crate) fn reconnecting(&mut self) {
+        self.set(LinkState::Reconnecting);
+    }
+
+    /// The serial task has stopped.
+    pub(crate) fn disconnected(&mut self) {
+        self.set(LinkState::Disconnected);
+    }
+
+    fn set(&mut self, state: LinkState) -> bool {
+        self.tx.send_if_modified(|current| {
+            let modified = *current != state;
+            *current = state;
+            modified
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn test_stale_after_timeout() {
+        let (tx, mut rx) = watch::channel(LinkState::Connecting);
+        let mut monitor = LinkMonitor::new(tx, TIMEOUT);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        // Waiting for the first frame does not count as stale
+        assert_eq!(monitor.stale_deadline(), None);
+        assert!(!monitor.check_stale(at(1000)));
+
+        monitor.received(at(0));
+        assert_eq!(*rx.borrow_and_update(), LinkState::Connected);
+        monitor.received(at(100));
+        assert!(!rx.has_changed().unwrap());
+        assert_eq!(monitor.stale_deadline(), Some(at(300)));
+        assert!(!monitor.check_stale(at(299)));
+        assert!(monitor.check_stale(at(300)));
+        assert_eq!(*rx.borrow_and_update(), LinkState::Stale);
+        assert!(!monitor.check_stale(at(400)));
+
+        monitor.received(at(400));
+        assert_eq!(*rx.borrow_and_update(), LinkState::Connected);
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let (tx, rx) = watch::channel(LinkState::Connecting);
+        let mut monitor = LinkMonitor::new(tx, TIMEOUT);
+        monitor.received(Instant::now());
+        monitor.reconnecting();
+        assert_eq!(*rx.borrow(), LinkState::Reconnecting);
+        assert_eq!(monitor.stale_deadline(), None);
+        monitor.connecting();
+        assert_eq!(*rx.borrow(), LinkState::Connecting);
+        monitor.disconnected();
+        assert_eq!(monitor.state(), LinkState::Disconnected);
+    }
+}
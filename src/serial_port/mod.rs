@@ -1,3 +1,24 @@
+mod builder;
+mod command_sender;
+mod emergency_stop;
+mod feedback_receiver;
+mod feedback_stream;
 mod handler;
+mod keep_alive;
+mod link_state;
+mod motion;
+mod velocity_source;
 
+pub use builder::SerialPortHandlerBuilder;
+pub use command_sender::CommandSender;
+use command_sender::Shared;
+use emergency_stop::EmergencyStopTriggers;
+pub use emergency_stop::{EmergencyStop, EmergencyStopCause};
+pub use feedback_receiver::{FeedbackReceiver, LagPolicy};
+pub use feedback_stream::{FeedbackStream, FeedbackStreamExt};
 pub use handler::SerialPortHandler;
+use keep_alive::KeepAlive;
+use link_state::LinkMonitor;
+pub use link_state::LinkState;
+use motion::Motion;
+pub use velocity_source::VelocitySource;
@@ -0,0 +1,173 @@
+use super::{EmergencyStopTriggers, KeepAlive, SerialPortHandlerBuilder, Shared};
+use crate::{
+    control::{HeadingHold, SafetyEvent, SafetySupervisor},
+    rx::Feedback,
+    tx::{ByteStream, commands::BaseControl},
+};
+use log::{info, warn};
+use std::sync::Arc;
+use tokio::{sync::broadcast, time::Instant};
+
+/// The velocity pipeline of the serial task.
+///
+/// Every velocity command passes through here before it is written to the port, whether it
+/// comes from the velocity target or from the command queue.
+pub(crate) struct Motion {
+    keep_alive: KeepAlive,
+    heading_hold: Option<HeadingHold>,
+    supervisor: Option<SafetySupervisor>,
+    triggers: EmergencyStopTriggers,
+    shared: Arc<Shared>,
+    safety_tx: broadcast::Sender<SafetyEvent>,
+    task_name: String,
+}
+
+impl Motion {
+    pub(crate) fn new(
+        config: &SerialPortHandlerBuilder,
+        shared: Arc<Shared>,
+        safety_tx: broadcast::Sender<SafetyEvent>,
+    ) -> Self {
+        Self {
+            keep_alive: KeepAlive::new(config.keep_alive_period, config.velocity_smoother),
+            heading_hold: config.heading_hold.map(HeadingHold::new),
+            supervisor: config.safety_policy.map(SafetySupervisor::new),
+            triggers: EmergencyStopTriggers::new(
+                config.emergency_stop_button,
+                config.heartbeat_timeout,
+            ),
+            shared,
+            safety_tx,
+            task_name: config.task_name.clone(),
+        }
+    }
+
+    /// Picks up a change in the velocity mux, and returns the command to write right away.
+    pub(crate) fn set_target(&mut self, now: Instant) -> Option<BaseControl> {
+        if self.emergency_stopped() {
+            return None;
+        }
+        let target = self.hold_heading(self.target(now));
+        let cmd = self.keep_alive.update(target, now)?;
+        Some(self.filter(cmd, now))
+    }
+
+    /// Returns the command to write on a keep-alive tick.
+    pub(crate) fn tick(&mut self, now: Instant) -> Option<BaseControl> {
+        let last_heartbeat = *self.shared.heartbeat_tx.borrow();
+        if let Some(cause) = self.triggers.heartbeat(last_heartbeat, now) {
+            self.shared.engage(cause);
+        }
+        let target = self.hold_heading(self.target(now));
+        if target.is_none() && self.keep_alive.is_active() {
+            warn!(
+                "{}: No velocity source refreshed within its timeout, stopping",
+                self.task_name
+            );
+        }
+        if let Some(cmd) = self
+            .supervisor
+            .as_mut()
+            .and_then(|supervisor| supervisor.override_command(now))
+        {
+            return Some(self.emergency_stop_filter(cmd));
+        }
+        let cmd = self.keep_alive.update(target, now)?;
+        Some(self.filter(cmd, now))
+    }
+
+    /// Reacts to a feedback frame, and returns a command to write right away.
+    pub(crate) fn feedback(&mut self, feedback: &Feedback, now: Instant) -> Option<BaseControl> {
+        if let Some(heading_hold) = &mut self.heading_hold {
+            heading_hold.update(feedback);
+        }
+        let bsd = feedback.basic_sensor_data.as_ref()?;
+        if let Some(cause) = self.triggers.button(bsd) {
+            self.shared.engage(cause);
+        }
+
+        let supervisor = self.supervisor.as_mut()?;
+        let mut triggered = false;
+        for event in supervisor.update(bsd, now) {
+            if let SafetyEvent::Triggered { cause, action } = event {
+                warn!("{}: Safety {:?} due to {}", self.task_name, action, cause);
+                triggered = true;
+            }
+            self.safety_tx.send(event).ok(); // no subscribers - ignore errors
+        }
+        if !triggered {
+            return None;
+        }
+        self.keep_alive.stopped();
+        let cmd = supervisor
+            .override_command(now)
+            .unwrap_or(*BaseControl::new(0, 0));
+        Some(self.emergency_stop_filter(cmd))
+    }
+
+    /// Releases every velocity source, so the base does not resume moving after a reset.
+    pub(crate) fn emergency_stop(&mut self) {
+        self.shared.mux_tx.send_modify(|mux| mux.release_all());
+        self.keep_alive.clear();
+    }
+
+    /// Applies the pipeline to the velocity command of a stream from the command queue.
+    pub(crate) fn filter_stream(&mut self, cmd: ByteStream, now: Instant) -> ByteStream {
+        if cmd.base_control().is_none() {
+            return cmd;
+        }
+        cmd.map_base_control(|velocity| self.filter(velocity, now))
+    }
+
+    /// Returns the command of the active velocity source, and publishes which one it is.
+    fn target(&self, now: Instant) -> Option<BaseControl> {
+        let mux = self.shared.mux_tx.borrow();
+        let name = mux.active(now).and_then(|id| mux.name(id));
+        self.shared.active_source_tx.send_if_modified(|active| {
+            if active.as_deref() == name {
+                return false;
+            }
+            if let Some(name) = name {
+                info!("{}: Velocity source {} in control", self.task_name, name);
+            }
+            *active = name.map(str::to_owned);
+            true
+        });
+        mux.command(now)
+    }
+
+    /// Corrects a straight target to keep the heading, if enabled.
+    fn hold_heading(&mut self, target: Option<BaseControl>) -> Option<BaseControl> {
+        match (&mut self.heading_hold, target) {
+            (Some(heading_hold), Some(cmd)) => Some(heading_hold.apply_command(cmd)),
+            (Some(heading_hold), None) => {
+                heading_hold.release();
+                None
+            }
+            (None, target) => target,
+        }
+    }
+
+    fn filter(&mut self, cmd: BaseControl, now: Instant) -> BaseControl {
+        let cmd = self.emergency_stop_filter(cmd);
+        let Some(supervisor) = self.supervisor.as_mut() else {
+            return cmd;
+        };
+        let (sent, event) = supervisor.filter(cmd, now);
+        if let Some(event) = event {
+            self.safety_tx.send(event).ok(); // no subscribers - ignore errors
+        }
+        sent
+    }
+
+    fn emergency_stop_filter(&self, cmd: BaseControl) -> BaseControl {
+        if self.emergency_stopped() {
+            return *BaseControl::new(0, 0);
+        }
+        cmd
+    }
+
+    fn emergency_stopped(&self) -> bool {
+        self.shared.estop_tx.borrow().is_engaged()
+    }
+}
@@ -0,0 +1,78 @@
+use super::Shared;
+use crate::control::SourceId;
+use std::sync::Arc;
+
+/// A producer of velocity commands registered with the velocity mux.
+///
+/// Obtained from [`super::SerialPortHandler::velocity_source`]. The source controls the base
+/// while it has the highest priority of the sources that published within their timeout.
+///
+/// Clones share the registration. Dropping the last clone unregisters the source, handing
+/// control to the next source in line right away.
+#[derive(Clone)]
+pub struct VelocitySource {
+    registration: Arc<Registration>,
+}
+
+/// Unregisters the source from the mux when dropped.
+struct Registration {
+    id: SourceId,
+    shared: Arc<Shared>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.shared.unregister(self.id);
+    }
+}
+
+impl VelocitySource {
+    pub(crate) fn new(id: SourceId, shared: Arc<Shared>) -> Self {
+        Self {
+            registration: Arc::new(Registration { id, shared }),
+        }
+    }
+
+    pub fn id(&self) -> SourceId {
+        self.registration.id
+    }
+
+    /// Publishes a velocity from this source.
+    ///
+    /// Fails with [`std::io::ErrorKind::PermissionDenied`] if the velocity moves the base
+    /// while the emergency stop is engaged.
+    pub fn set_velocity(&self, speed: i16, radius: i16) -> std::io::Result<()> {
+        let Registration { id, shared } = &*self.registration;
+        shared.publish(*id, speed, radius)
+    }
+
+    /// Gives up control right away, handing it back to the next source in line.
+    pub fn release(&self) {
+        let Registration { id, shared } = &*self.registration;
+        shared.release(*id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn test_drop_last_clone_unregisters() {
+        let shared = Arc::new(Shared::new(Duration::from_secs(2)));
+        let id = shared.register("teleop", 10, Duration::from_millis(500));
+        let source = VelocitySource::new(id, shared.clone());
+        let clone = source.clone();
+        source.set_velocity(100, 0).unwrap();
+        let active = || shared.mux_tx.borrow().active(Instant::now());
+
+        drop(source);
+        assert_eq!(active(), Some(id));
+        drop(clone);
+        assert_eq!(active(), None);
+        // Only the default source is left
+        assert_eq!(shared.mux_tx.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,35 @@
+//! Feedback frames for the unit tests.
+
+use crate::rx::{BasicSensorData, Feedback, InertialSensor};
+
+/// Basic sensor data at `timestamp` in ms, with the given encoder readings.
+pub(crate) fn sensors(timestamp: u16, left_encoder: u16, right_encoder: u16) -> BasicSensorData {
+    BasicSensorData {
+        timestamp,
+        left_encoder,
+        right_encoder,
+        ..Default::default()
+    }
+}
+
+/// Inertial sensor data with the gyro `angle` in degrees and `angle_rate` in deg/s.
+pub(crate) fn inertial(angle: f32, angle_rate: f32) -> InertialSensor {
+    InertialSensor { angle, angle_rate }
+}
+
+/// A frame holding only inertial sensor data, with the gyro `angle` in degrees.
+pub(crate) fn inertial_frame(angle: f32) -> Feedback {
+    Feedback {
+        inertial_sensor: Some(inertial(angle, 0.0)),
+        ..Default::default()
+    }
+}
+
+/// A frame holding the basic sensor data, and the inertial sensor data if given.
+pub(crate) fn frame(bsd: BasicSensorData, inertial: Option<InertialSensor>) -> Feedback {
+    Feedback {
+        basic_sensor_data: Some(bsd),
+        inertial_sensor: inertial,
+        ..Default::default()
+    }
+}
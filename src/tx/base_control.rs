@@ -1,5 +1,6 @@
 use crate::tx::protocol::{CommandIds, ToSubPayload};
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct BaseControl {
     speed: i16,
     radius: i16,
@@ -9,6 +10,31 @@ impl BaseControl {
     pub fn new(speed: i16, radius: i16) -> Box<Self> {
         Box::new(BaseControl { speed, radius })
     }
+
+    /// Speed in mm/s.
+    pub fn speed(&self) -> i16 {
+        self.speed
+    }
+
+    /// Radius in mm. Zero means driving straight, and 1 means turning on the spot.
+    pub fn radius(&self) -> i16 {
+        self.radius
+    }
+
+    /// Decodes a sub-payload produced by [`ToSubPayload::to_subpayload`].
+    pub(crate) fn from_subpayload(data: &[u8]) -> Option<Self> {
+        match data {
+            [id, 4, speed_lo, speed_hi, radius_lo, radius_hi]
+                if *id == CommandIds::BaseControl as u8 =>
+            {
+                Some(BaseControl {
+                    speed: i16::from_le_bytes([*speed_lo, *speed_hi]),
+                    radius: i16::from_le_bytes([*radius_lo, *radius_hi]),
+                })
+            }
+            _ => None,
+        }
+    }
 }
 
 impl ToSubPayload for BaseControl {
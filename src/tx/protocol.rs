@@ -1,3 +1,5 @@
+use crate::tx::base_control::BaseControl;
+
 pub struct ByteStream {
     subpayloads: Vec<Box<dyn ToSubPayload + Send + Sync>>,
 }
@@ -18,6 +20,26 @@ impl ByteStream {
         self
     }
 
+    /// Returns the base control command of the stream, if it has one.
+    pub(crate) fn base_control(&self) -> Option<BaseControl> {
+        self.subpayloads
+            .iter()
+            .find_map(|subpayload| BaseControl::from_subpayload(&subpayload.to_subpayload()))
+    }
+
+    /// Replaces the base control command of the stream using `f`.
+    pub(crate) fn map_base_control(
+        mut self,
+        mut f: impl FnMut(BaseControl) -> BaseControl,
+    ) -> Self {
+        for subpayload in &mut self.subpayloads {
+            if let Some(cmd) = BaseControl::from_subpayload(&subpayload.to_subpayload()) {
+                *subpayload = Box::new(f(cmd));
+            }
+        }
+        self
+    }
+
     pub fn to_bytes(self) -> Vec<u8> {
         let mut payload = Vec::new();
         for subpayload in &self.subpayloads {
@@ -57,7 +79,8 @@ pub enum CommandIds {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tx::base_control::BaseControl;
+    use crate::tx::sound::Sound;
+    use std::time::Duration;
 
     #[test]
     fn test_to_bytes() {
@@ -69,4 +92,22 @@ mod tests {
             vec![0xAA, 0x55, 0x06, 0x01, 0x04, 0x64, 0x00, 0x9C, 0xFF, 0x04]
         );
     }
+
+    #[test]
+    fn test_map_base_control() {
+        let stream = ByteStream::builder()
+            .subpayload(Sound::new(440.0, Duration::from_millis(100)))
+            .subpayload(BaseControl::new(100, -100));
+        assert_eq!(stream.base_control(), Some(*BaseControl::new(100, -100)));
+
+        let stream = stream.map_base_control(|_| *BaseControl::new(0, 0));
+        assert_eq!(stream.base_control(), Some(*BaseControl::new(0, 0)));
+        assert_eq!(
+            stream.to_bytes(),
+            vec![
+                0xAA, 0x55, 0x0B, 0x03, 0x03, 0x3A, 0x03, 0x64, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00,
+                0x53
+            ]
+        );
+    }
 }